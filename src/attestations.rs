@@ -0,0 +1,214 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// A single artifact whose hash differs between two signers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub artifact: String,
+    pub ours: String,
+    pub theirs: String,
+}
+
+/// The result of comparing our SHA256SUMS against a single other signer.
+#[derive(Debug, Clone, Default)]
+pub struct SignerComparison {
+    pub signer: String,
+    pub matched: usize,
+    pub diverged: Vec<Divergence>,
+    pub only_ours: Vec<String>,
+    pub only_theirs: Vec<String>,
+}
+
+impl SignerComparison {
+    pub fn is_match(&self) -> bool {
+        self.diverged.is_empty()
+    }
+}
+
+/// Comparison of our attestation file against every other signer for a version.
+#[derive(Debug, Clone)]
+pub struct AttestationReport {
+    pub version: String,
+    pub file_name: String,
+    pub comparisons: Vec<SignerComparison>,
+}
+
+impl AttestationReport {
+    pub fn has_mismatches(&self) -> bool {
+        self.comparisons.iter().any(|c| !c.is_match())
+    }
+}
+
+impl fmt::Display for AttestationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Comparing {} for {}:", self.file_name, self.version)?;
+        if self.comparisons.is_empty() {
+            return writeln!(f, "  No other signers found");
+        }
+        writeln!(
+            f,
+            "  {:<24} {:<8} {:>8} {:>8} {:>10} {:>12}",
+            "Signer", "Status", "Matched", "Differ", "Only ours", "Only theirs"
+        )?;
+        for c in &self.comparisons {
+            writeln!(
+                f,
+                "  {:<24} {:<8} {:>8} {:>8} {:>10} {:>12}",
+                c.signer,
+                if c.is_match() { "MATCH" } else { "DIFFER" },
+                c.matched,
+                c.diverged.len(),
+                c.only_ours.len(),
+                c.only_theirs.len()
+            )?;
+        }
+        for c in self.comparisons.iter().filter(|c| !c.is_match()) {
+            writeln!(f, "  Divergent artifacts for {}:", c.signer)?;
+            for d in &c.diverged {
+                writeln!(f, "    {}", d.artifact)?;
+                writeln!(f, "      ours:   {}", d.ours)?;
+                writeln!(f, "      theirs: {}", d.theirs)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse the contents of a SHA256SUMS file into a map of artifact name to hash.
+pub fn parse_sha256sums(contents: &str) -> Result<BTreeMap<String, String>> {
+    let mut sums = BTreeMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (hash, artifact) = line
+            .split_once(char::is_whitespace)
+            .with_context(|| format!("Malformed SHA256SUMS line {}: {:?}", i + 1, line))?;
+        sums.insert(
+            artifact.trim_start().trim_start_matches('*').to_string(),
+            hash.to_string(),
+        );
+    }
+    Ok(sums)
+}
+
+/// Compare our parsed SHA256SUMS with another signer's.
+pub fn compare_sums(
+    signer: &str,
+    ours: &BTreeMap<String, String>,
+    theirs: &BTreeMap<String, String>,
+) -> SignerComparison {
+    let mut comparison = SignerComparison {
+        signer: signer.to_string(),
+        ..Default::default()
+    };
+    for (artifact, hash) in ours {
+        match theirs.get(artifact) {
+            Some(their_hash) if their_hash == hash => comparison.matched += 1,
+            Some(their_hash) => comparison.diverged.push(Divergence {
+                artifact: artifact.clone(),
+                ours: hash.clone(),
+                theirs: their_hash.clone(),
+            }),
+            None => comparison.only_ours.push(artifact.clone()),
+        }
+    }
+    comparison.only_theirs = theirs
+        .keys()
+        .filter(|artifact| !ours.contains_key(*artifact))
+        .cloned()
+        .collect();
+    comparison
+}
+
+/// Compare our attestation file for `version` with those of every other signer in guix.sigs.
+///
+/// # Arguments
+///
+/// * `guix_sigs_dir` - Path to the local guix.sigs checkout
+/// * `version` - The guix.sigs version directory name (e.g. "27.1")
+/// * `signer_name` - Our signer name
+/// * `file_name` - The attestation file to compare (e.g. "noncodesigned.SHA256SUMS")
+///
+/// # Returns
+///
+/// A Result containing an AttestationReport, or an error if our own file could not be read.
+pub fn compare_attestations(
+    guix_sigs_dir: &Path,
+    version: &str,
+    signer_name: &str,
+    file_name: &str,
+) -> Result<AttestationReport> {
+    let version_dir = guix_sigs_dir.join(version);
+    let our_path = version_dir.join(signer_name).join(file_name);
+    let ours = parse_sha256sums(
+        &fs::read_to_string(&our_path)
+            .with_context(|| format!("Failed to read our attestations: {:?}", our_path))?,
+    )?;
+
+    let mut signers: Vec<String> = fs::read_dir(&version_dir)
+        .with_context(|| format!("Failed to read directory: {:?}", version_dir))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name != signer_name)
+        .collect();
+    signers.sort();
+
+    let mut comparisons = Vec::new();
+    for signer in signers {
+        let path = version_dir.join(&signer).join(file_name);
+        if !path.exists() {
+            continue;
+        }
+        let theirs = parse_sha256sums(
+            &fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read attestations: {:?}", path))?,
+        )
+        .with_context(|| format!("Failed to parse attestations from {}", signer))?;
+        comparisons.push(compare_sums(&signer, &ours, &theirs));
+    }
+
+    Ok(AttestationReport {
+        version: version.to_string(),
+        file_name: file_name.to_string(),
+        comparisons,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_sums() {
+        let ours = parse_sha256sums(
+            "aaaa  bitcoin-27.1-x86_64-linux-gnu.tar.gz\nbbbb  bitcoin-27.1-arm64-apple-darwin.tar.gz\n",
+        )
+        .unwrap();
+        let same = ours.clone();
+        let theirs = parse_sha256sums(
+            "aaaa  bitcoin-27.1-x86_64-linux-gnu.tar.gz\ncccc  bitcoin-27.1-arm64-apple-darwin.tar.gz\ndddd  bitcoin-27.1-win64-setup.exe\n",
+        )
+        .unwrap();
+
+        assert!(compare_sums("alice", &ours, &same).is_match());
+
+        let c = compare_sums("bob", &ours, &theirs);
+        assert!(!c.is_match());
+        assert_eq!(c.matched, 1);
+        assert_eq!(
+            c.diverged,
+            vec![Divergence {
+                artifact: "bitcoin-27.1-arm64-apple-darwin.tar.gz".to_string(),
+                ours: "bbbb".to_string(),
+                theirs: "cccc".to_string(),
+            }]
+        );
+        assert_eq!(c.only_theirs, vec!["bitcoin-27.1-win64-setup.exe"]);
+    }
+}
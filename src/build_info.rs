@@ -0,0 +1,148 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Metadata about a guix build, recorded next to the build outputs so it can be reported when
+/// the outputs are attested later.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    pub host: String,
+    pub guix_version: String,
+    pub guix_commit: String,
+    /// Seconds since the Unix epoch at which the build started.
+    pub started_at: u64,
+    pub duration_secs: u64,
+}
+
+impl BuildInfo {
+    /// Collect metadata about the build host and Guix installation for a finished build of the
+    /// bitcoin checkout in `bitcoin_dir`.
+    ///
+    /// The Guix commit is the one `contrib/guix` pins for its `guix time-machine`, which is what
+    /// actually produced the outputs, rather than the host's own Guix.
+    pub fn collect(bitcoin_dir: &Path, started: SystemTime, duration: Duration) -> Self {
+        let hostname = command_stdout("hostname", &[]).unwrap_or_else(|| "unknown".to_string());
+        let guix_version = command_stdout("guix", &["--version"])
+            .and_then(|v| {
+                v.lines()
+                    .next()
+                    .map(|l| l.trim_start_matches("guix (GNU Guix) ").to_string())
+            })
+            .unwrap_or_else(|| "unknown".to_string());
+        let guix_commit = fs::read_to_string(bitcoin_dir.join("contrib/guix/libexec/prelude.bash"))
+            .ok()
+            .and_then(|prelude| parse_pinned_guix_commit(&prelude))
+            .unwrap_or_else(|| "unknown".to_string());
+
+        Self {
+            host: format!(
+                "{} ({} {})",
+                hostname,
+                std::env::consts::OS,
+                std::env::consts::ARCH
+            ),
+            guix_version,
+            guix_commit,
+            started_at: started
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            duration_secs: duration.as_secs(),
+        }
+    }
+
+    /// Load build metadata from `path`.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the BuildInfo, or None if no metadata was recorded for the build.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read build info: {:?}", path))?;
+        let info = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse build info: {:?}", path))?;
+        Ok(Some(info))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents =
+            serde_json::to_string_pretty(self).context("Failed to serialize build info")?;
+        fs::write(path, contents).with_context(|| format!("Failed to write build info: {:?}", path))
+    }
+
+    /// The build duration formatted as e.g. "2h 05m 13s".
+    pub fn duration(&self) -> String {
+        let (h, m, s) = (
+            self.duration_secs / 3600,
+            self.duration_secs % 3600 / 60,
+            self.duration_secs % 60,
+        );
+        if h > 0 {
+            format!("{}h {:02}m {:02}s", h, m, s)
+        } else {
+            format!("{}m {:02}s", m, s)
+        }
+    }
+}
+
+fn command_stdout(command: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(command).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Extract the commit passed to `guix time-machine` in `contrib/guix/libexec/prelude.bash`.
+fn parse_pinned_guix_commit(prelude: &str) -> Option<String> {
+    prelude
+        .lines()
+        .skip_while(|line| !line.contains("guix time-machine"))
+        .find_map(|line| {
+            line.split_whitespace()
+                .find_map(|arg| arg.strip_prefix("--commit="))
+                .map(|commit| commit.trim_matches(|c| c == '"' || c == '\'').to_string())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pinned_guix_commit() {
+        let prelude = r#"
+time-machine() {
+    # shellcheck disable=SC2086
+    guix time-machine --url=https://codeberg.org/guix/guix.git \
+                      --commit=53396a22afc04536ddf75d8f82ad2eafa5082725 \
+                      --cores="$JOBS" \
+                      --keep-failed \
+                      --fallback \
+                      ${SUBSTITUTE_URLS:+--substitute-urls="$SUBSTITUTE_URLS"} \
+                      ${ADDITIONAL_GUIX_COMMON_FLAGS} ${ADDITIONAL_GUIX_TIMEMACHINE_FLAGS} \
+                      -- "$@"
+}
+"#;
+        assert_eq!(
+            parse_pinned_guix_commit(prelude).as_deref(),
+            Some("53396a22afc04536ddf75d8f82ad2eafa5082725")
+        );
+        assert_eq!(parse_pinned_guix_commit("guix build --commit=abc"), None);
+
+        let info = BuildInfo {
+            host: "builder".to_string(),
+            guix_version: "1.4.0".to_string(),
+            guix_commit: "8e2f32c".to_string(),
+            started_at: 0,
+            duration_secs: 7513,
+        };
+        assert_eq!(info.duration(), "2h 05m 13s");
+    }
+}
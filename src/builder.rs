@@ -1,27 +1,33 @@
 use anyhow::{bail, Context, Result};
 use flate2::read::GzDecoder;
 use log::{debug, error, info, warn};
-use octocrab::Octocrab;
 use regex::Regex;
-use std::cmp::Ordering;
-use std::env;
 use std::fmt;
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, IsTerminal};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
 use tar::Archive;
 
+use crate::attestations::{compare_attestations, parse_sha256sums};
+use crate::build_info::BuildInfo;
 use crate::config::Config;
-use crate::config::GH_TOKEN_NAME;
-use crate::version::compare_versions;
+use crate::forge::{forge_from_config, upstream_url, Forge};
+use crate::logging::StepLog;
+use crate::notify::notify;
+use crate::process::{child_groups_managed, register_group, shutdown_requested};
+use crate::signing::{sign_attestations, signing_backend};
+use crate::version::BitcoinVersion;
 use crate::xor::xor_decrypt;
 
 #[derive(Debug, Default, Clone)]
 pub struct BuildArgs {
     pub action: BuildAction,
     pub auto: bool,
-    pub tag: Option<String>,
+    pub tag: Option<BitcoinVersion>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -35,10 +41,13 @@ pub enum BuildAction {
     Warmup,
 }
 
+/// Host triplet fragments of platforms whose outputs are codesigned by guix-codesign.
+const CODESIGNED_HOSTS: [&str; 2] = ["darwin", "mingw32"];
+
 pub struct Builder {
     config: Config,
     args: BuildArgs,
-    octo: Option<Octocrab>,
+    forge: Option<Box<dyn Forge>>,
 }
 
 impl fmt::Display for Builder {
@@ -72,23 +81,18 @@ impl Builder {
     pub fn new(config: Config, args: BuildArgs) -> Result<Self> {
         if let Some(ref v) = args.tag {
             println!("{}", v);
-            if compare_versions(v, "v21.0") == Ordering::Less {
+            if *v < "v21.0".parse::<BitcoinVersion>()? {
                 bail!("Can't build tags earlier than v0.21.0");
             }
         }
 
-        let octo = env::var(GH_TOKEN_NAME)
-            .ok()
-            .filter(|s| !s.is_empty())
-            .map(|token| {
-                Octocrab::builder()
-                    .personal_token(token)
-                    .build()
-                    .context(format!("Couldn't build Octocrab from {}", GH_TOKEN_NAME))
-            })
-            .transpose()?;
+        let forge = forge_from_config(&config)?;
 
-        Ok(Self { config, args, octo })
+        Ok(Self {
+            config,
+            args,
+            forge,
+        })
     }
 
     pub async fn init(&self) -> Result<()> {
@@ -100,7 +104,7 @@ impl Builder {
 
         if !self.config.bitcoin_dir.exists() {
             info!("Cloning bitcoin repository");
-            self.run_command(
+            run_command(
                 &self.config.guix_build_dir,
                 "git",
                 &[
@@ -122,7 +126,7 @@ impl Builder {
         // Clone bitcoin-detached-sigs if it doesn't exist
         if !self.config.bitcoin_detached_sigs_dir.exists() {
             info!("Cloning bitcoin-detached-sigs repository");
-            self.run_command(
+            run_command(
                 &self.config.guix_build_dir,
                 "git",
                 &[
@@ -151,44 +155,7 @@ impl Builder {
                 .context("Failed to create macos_sdks_dir")?;
         }
 
-        // Clone guix.sigs if it doesn't exist
-        if !self.config.guix_sigs_dir.exists() {
-            info!("Cloning guix.sigs repository");
-            self.run_command(
-                &self.config.guix_build_dir,
-                "git",
-                &[
-                    "clone",
-                    "--origin",
-                    "upstream",
-                    format!(
-                        "https://github.com/{}/{}.git",
-                        self.config.guix_sigs_repo_owner.clone(),
-                        self.config.guix_sigs_repo_name.clone()
-                    )
-                    .as_str(),
-                    self.config
-                        .guix_sigs_dir
-                        .file_name()
-                        .unwrap()
-                        .to_str()
-                        .unwrap(),
-                ],
-            )
-            .context("Failed to clone guix.sigs repository")?;
-
-            // Set the origin remote
-            self.run_command(
-                &self.config.guix_sigs_dir,
-                "git",
-                &["remote", "add", "origin", &self.config.guix_sigs_fork_url],
-            )?;
-
-            info!(
-                "Set origin remote of the guix sigs repo to: {}",
-                &self.config.guix_sigs_fork_url
-            );
-        }
+        clone_guix_sigs(&self.config)?;
 
         // Check if the GPG key is available
         let output = Command::new("gpg")
@@ -300,10 +267,21 @@ impl Builder {
     }
 
     pub async fn run(&self) -> Result<()> {
+        self.run_action().await?;
+        Ok(())
+    }
+
+    /// Run the build action.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing true if the attestations were pushed to an open pull request.
+    pub async fn run_action(&self) -> Result<bool> {
         info!(
             "Starting build action {:?} for tag {:?}",
             self.args.action, self.args.tag
         );
+        let mut pull_request_open = false;
         match self.args.action {
             BuildAction::None => {}
             BuildAction::Build => {
@@ -311,20 +289,30 @@ impl Builder {
                     .context("Failed to refresh repositories")?;
                 self.checkout_bitcoin(false)
                     .context("Failed to checkout Bitcoin")?;
-                self.check_sdk().await.context("Failed to check SDK")?;
+                if self.builds_darwin() {
+                    self.check_sdk().await.context("Failed to check SDK")?;
+                }
                 self.guix_build().context("Failed to build with Guix")?;
             }
             BuildAction::NonCodeSigned => {
                 self.checkout_bitcoin(false)
                     .context("Failed to checkout Bitcoin")?;
-                self.guix_attest("non-codesigned").await?;
+                pull_request_open = self.guix_attest("non-codesigned").await?;
+            }
+            BuildAction::CodeSigned
+                if self.is_partial_build() && self.codesigned_hosts().is_empty() =>
+            {
+                warn!(
+                    "None of the configured hosts {:?} are codesigned. Skipping codesigning.",
+                    self.config.hosts
+                );
             }
             BuildAction::CodeSigned => {
                 self.checkout_bitcoin(false)
                     .context("Failed to checkout Bitcoin")?;
                 self.guix_codesign()
                     .context("Failed to codesign binaries")?;
-                self.guix_attest("codesigned").await?;
+                pull_request_open = self.guix_attest("codesigned").await?;
             }
             BuildAction::Clean => self
                 .guix_clean()
@@ -338,7 +326,26 @@ impl Builder {
                 self.guix_build().context("Failed to build with Guix")?;
             }
         }
-        Ok(())
+        Ok(pull_request_open)
+    }
+
+    /// Whether only a subset of hosts is built, so no complete all.SHA256SUMS can be produced.
+    fn is_partial_build(&self) -> bool {
+        !self.config.hosts.is_empty()
+    }
+
+    fn builds_darwin(&self) -> bool {
+        !self.is_partial_build() || self.config.hosts.iter().any(|h| h.contains("darwin"))
+    }
+
+    /// The configured hosts that guix-codesign needs to sign.
+    fn codesigned_hosts(&self) -> Vec<String> {
+        self.config
+            .hosts
+            .iter()
+            .filter(|h| CODESIGNED_HOSTS.iter().any(|c| h.contains(c)))
+            .cloned()
+            .collect()
     }
 
     fn checkout_bitcoin(&self, warmup: bool) -> Result<()> {
@@ -361,12 +368,13 @@ impl Builder {
             .as_ref()
             .ok_or_else(|| anyhow::anyhow!("Tag not set"))?;
         info!("Checking out Bitcoin tag {}", tag);
+        let tag = tag.to_string();
 
         // Fetch the tag
         let mut command = Command::new("git");
         command
             .current_dir(&self.config.bitcoin_dir)
-            .args(["fetch", "origin", "tag", tag, "--no-tags", "--depth=1"])
+            .args(["fetch", "origin", "tag", &tag, "--no-tags", "--depth=1"])
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
         self.run_command_with_output(command)?;
@@ -375,7 +383,7 @@ impl Builder {
         let mut command = Command::new("git");
         command
             .current_dir(&self.config.bitcoin_dir)
-            .args(["checkout", tag])
+            .args(["checkout", &tag])
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
         self.run_command_with_output(command).context(format!(
@@ -388,25 +396,25 @@ impl Builder {
 
     fn refresh_repos(&self) -> Result<()> {
         info!("Refreshing guix.sigs and bitcoin-detached-sigs repos");
-        self.run_command(
+        run_command(
             &self.config.guix_build_dir.join("guix.sigs"),
             "git",
             &["checkout", "main"],
         )
         .context("Failed to checkout main branch in guix.sigs")?;
-        self.run_command(
+        run_command(
             &self.config.guix_build_dir.join("guix.sigs"),
             "git",
             &["pull", "upstream", "main"],
         )
         .context("Failed to pull upstream main in guix.sigs")?;
-        self.run_command(
+        run_command(
             &self.config.guix_build_dir.join("bitcoin-detached-sigs"),
             "git",
             &["checkout", "master"],
         )
         .context("Failed to checkout master branch in bitcoin-detached-sigs")?;
-        self.run_command(
+        run_command(
             &self.config.guix_build_dir.join("bitcoin-detached-sigs"),
             "git",
             &["pull", "origin", "master"],
@@ -438,13 +446,61 @@ impl Builder {
                 .env("ADDITIONAL_GUIX_COMMON_FLAGS", "--max-jobs=8");
         }
 
+        if self.is_partial_build() {
+            info!("Building hosts: {}", self.config.hosts.join(" "));
+            command.env("HOSTS", self.config.hosts.join(" "));
+        }
+
+        let (started, timer) = (SystemTime::now(), Instant::now());
         self.run_command_with_output(command)
             .context("Failed to execute guix-build command")?;
+
+        let build_info = BuildInfo::collect(&self.config.bitcoin_dir, started, timer.elapsed());
+        info!("Build finished in {}", build_info.duration());
+        // Warmup builds master, which is never attested, so only tags get build info. It is only
+        // reported in pull requests, so failing to save it doesn't fail the build.
+        if self.args.tag.is_some() {
+            if let Err(e) = self
+                .build_info_path()
+                .and_then(|path| build_info.save(&path))
+            {
+                warn!("Failed to save build info: {:#}", e);
+            }
+        }
         Ok(())
     }
 
-    async fn guix_attest(&self, a_type: &str) -> Result<()> {
+    /// Attest to the build outputs, then sign, commit and publish the attestations.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing true if the attestations were pushed to an open pull request.
+    async fn guix_attest(&self, a_type: &str) -> Result<bool> {
         info!("Attesting {} binaries", a_type);
+        let backend = signing_backend(&self.config);
+        // Someone at a terminal can answer a pinentry prompt, so only unattended runs need to
+        // know up front that signing would block
+        let unattended = self.args.auto || !std::io::stdin().is_terminal();
+        let preflight = if unattended {
+            backend.preflight()
+        } else {
+            Ok(())
+        };
+        if let Err(blocked) = preflight {
+            error!("{}", blocked);
+            let tag = self
+                .args
+                .tag
+                .as_ref()
+                .map(|t| t.to_string())
+                .unwrap_or_default();
+            notify(
+                &self.config,
+                "signing-blocked",
+                &format!("{} attestation of {}: {}", a_type, tag, blocked),
+            );
+            return Err(blocked.into());
+        }
         let mut command = Command::new(self.config.bitcoin_dir.join("contrib/guix/guix-attest"));
         command
             .current_dir(&self.config.bitcoin_dir)
@@ -466,12 +522,76 @@ impl Builder {
             )
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
+        backend.configure_attest(&mut command);
 
         self.run_command_with_output(command)
             .context("Failed to execute guix-attest command")?;
-        self.commit_attestations(a_type, self.octo.as_ref())
+        if let Some(tag) = &self.args.tag {
+            let signer_dir = self
+                .config
+                .guix_build_dir
+                .join("guix.sigs")
+                .join(tag.dir_name())
+                .join(&self.config.signer_name);
+            sign_attestations(backend.as_ref(), &self.config, &signer_dir)
+                .context("Failed to sign attestations")?;
+        }
+        self.compare_with_other_signers(a_type)
+            .context("Failed to compare attestations with other signers")?;
+        self.commit_attestations(a_type, self.forge.as_deref())
             .await
-            .context("Failed to commit attestations")?;
+            .context("Failed to commit attestations")
+    }
+
+    /// Where guix_build records the BuildInfo for the current tag.
+    fn build_info_path(&self) -> Result<PathBuf> {
+        let tag = self
+            .args
+            .tag
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Tag not set"))?;
+        Ok(self
+            .config
+            .bitcoin_dir
+            .join(format!("guix-build-{}", tag.dir_name()))
+            .join("bgt-build-info.json"))
+    }
+
+    fn compare_with_other_signers(&self, a_type: &str) -> Result<()> {
+        let tag = self
+            .args
+            .tag
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Tag not set"))?;
+        let file_name = comparison_file_name(a_type);
+        let report = compare_attestations(
+            &self.config.guix_sigs_dir,
+            &tag.dir_name(),
+            &self.config.signer_name,
+            file_name,
+        )?;
+        println!("{}", report);
+
+        if report.has_mismatches() {
+            if self.args.auto {
+                bail!(
+                    "Our {} for {} differs from other signers. Not opening a pull request.",
+                    file_name,
+                    tag
+                );
+            }
+            error!(
+                "Our {} for {} differs from other signers. Check the build is reproducible before opening a pull request.",
+                file_name, tag
+            );
+        } else {
+            info!(
+                "{} for {} matches all {} other signers",
+                file_name,
+                tag,
+                report.comparisons.len()
+            );
+        }
         Ok(())
     }
 
@@ -491,6 +611,10 @@ impl Builder {
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
 
+        if self.is_partial_build() {
+            command.env("HOSTS", self.codesigned_hosts().join(" "));
+        }
+
         self.run_command_with_output(command)
             .context("Failed to execute guix-codesign command")?;
         Ok(())
@@ -509,73 +633,200 @@ impl Builder {
         Ok(())
     }
 
+    /// Commit the attestations to their branch and publish it.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing true if the branch was pushed to an open pull request.
     async fn commit_attestations(
         &self,
         attestation_type: &str,
-        build: Option<&Octocrab>,
-    ) -> Result<()> {
+        forge: Option<&dyn Forge>,
+    ) -> Result<bool> {
         info!("Committing attestations");
         let tag = self
             .args
             .tag
             .as_ref()
             .ok_or_else(|| anyhow::anyhow!("Tag not set"))?;
-        let branch_name = format!("{}-{}-attestations", tag, attestation_type);
+        let mut branch_name = format!("{}-{}-attestations", tag, attestation_type);
+        let mut base = "main".to_string();
         let commit_message = format!(
             "Add {} attestations by {} for {}",
             attestation_type, self.config.signer_name, tag
         );
 
-        // Create new branch
+        if attestation_type == "codesigned" {
+            if let Some(open_branch) = self.aggregate_branch(tag, forge).await? {
+                info!("Adding codesigned attestations to branch {}", open_branch);
+                branch_name = open_branch.clone();
+                base = open_branch;
+            }
+        }
+
+        let add_files = self.attestation_files(tag, attestation_type)?;
+        if self.config.sign_commits {
+            self.check_commit_signing()?;
+        }
+
+        let committed = self.commit_to_branch(&branch_name, &base, &commit_message, &add_files);
+        let result = match committed {
+            Ok(true) => {
+                self.publish_attestations(
+                    attestation_type,
+                    &branch_name,
+                    &commit_message,
+                    &add_files,
+                    forge,
+                )
+                .await
+            }
+            Ok(false) => Ok(false),
+            Err(e) => Err(e),
+        };
+
+        // Always leave guix.sigs on a clean main, even if committing or pushing failed
+        let guix_sigs = self.config.guix_build_dir.join("guix.sigs");
+        let cleanup = run_command(&guix_sigs, "git", &["checkout", "main"])
+            .context("Failed to return guix.sigs to the main branch")
+            .and_then(|_| {
+                run_command(&guix_sigs, "git", &["reset", "--quiet"])
+                    .context("Failed to unstage changes in guix.sigs")
+            });
+
+        match (result, cleanup) {
+            (Err(e), Err(cleanup)) => {
+                Err(e.context(format!("Cleaning up afterwards also failed: {:#}", cleanup)))
+            }
+            (Err(e), Ok(())) | (Ok(_), Err(e)) => Err(e),
+            (Ok(published), Ok(())) => Ok(published),
+        }
+    }
+
+    /// Check that git's user.email in guix.sigs is a user ID of gpg_key_id, which commits are
+    /// signed with, as the forge only shows signed commits as verified if it is.
+    fn check_commit_signing(&self) -> Result<()> {
+        let Some(email) = self.git_output(&["config", "user.email"])? else {
+            bail!(
+                "git user.email is not set. Set it to an email address of GPG key {} in {:?}",
+                self.config.gpg_key_id,
+                self.config.guix_sigs_dir
+            );
+        };
+        let output = Command::new("gpg")
+            .args([
+                "--batch",
+                "--with-colons",
+                "--list-keys",
+                &self.config.gpg_key_id,
+            ])
+            .output()
+            .context("Failed to execute gpg command")?;
+        if !output.status.success() {
+            bail!(
+                "GPG key {} not found: {}",
+                self.config.gpg_key_id,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let uids = String::from_utf8_lossy(&output.stdout);
+        let email_on_key = uids
+            .lines()
+            .filter(|line| line.starts_with("uid:"))
+            .filter_map(|line| line.split(':').nth(9))
+            .any(|uid| uid.contains(&format!("<{}>", email)));
+        if !email_on_key {
+            bail!(
+                "git user.email ({}) in {:?} is not a user ID of GPG key {}, so signed commits would not show as verified",
+                email,
+                self.config.guix_sigs_dir,
+                self.config.gpg_key_id
+            );
+        }
+        Ok(())
+    }
+
+    /// The non-codesigned attestation branch to add the codesigned attestations to, if they are
+    /// aggregated into one pull request and that branch's pull request is still open.
+    ///
+    /// Draft mode always aggregates, so the draft can be marked ready once codesigned.
+    async fn aggregate_branch(
+        &self,
+        tag: &BitcoinVersion,
+        forge: Option<&dyn Forge>,
+    ) -> Result<Option<String>> {
+        if !self.config.aggregate_pull_requests && !self.config.draft_pull_requests {
+            return Ok(None);
+        }
+        let branch = format!("{}-non-codesigned-attestations", tag);
+        if self
+            .git_output(&["rev-parse", "--verify", "--quiet", &branch])?
+            .is_none()
+        {
+            return Ok(None);
+        }
+
+        let username = self
+            .config
+            .github_username
+            .as_deref()
+            .filter(|s| !s.is_empty());
+        let is_open = match (self.args.auto, forge, username) {
+            (true, Some(forge), Some(username)) => forge
+                .find_open_pull_request(username, &branch)
+                .await?
+                .is_some(),
+            // Without a forge to ask, treat the branch as open until it is merged into main
+            _ => !self.git_succeeds(&["merge-base", "--is-ancestor", &branch, "main"])?,
+        };
+        if !is_open {
+            info!(
+                "Pull request for {} is no longer open, opening a new one",
+                branch
+            );
+            return Ok(None);
+        }
+        Ok(Some(branch))
+    }
+
+    /// Commit the attestation files to `branch_name`, creating or resetting the branch from `base`.
+    ///
+    /// Rerunning an attestation reuses the existing branch commit when the attestations are
+    /// unchanged, so reruns after fixing e.g. GPG issues do not fail or create duplicate commits.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing true if the branch has attestations to publish, or false if they are
+    /// already on main.
+    fn commit_to_branch(
+        &self,
+        branch_name: &str,
+        base: &str,
+        commit_message: &str,
+        add_files: &[String],
+    ) -> Result<bool> {
+        let guix_sigs = self.config.guix_build_dir.join("guix.sigs");
+        let existing = self.git_output(&["rev-parse", "--verify", "--quiet", branch_name])?;
+        if let Some(sha) = &existing {
+            info!("Reusing existing branch {} at {}", branch_name, sha);
+        }
+
+        // Create the branch, or reset an existing one, on top of the base
         let mut command = Command::new("git");
         command
-            .current_dir(self.config.guix_build_dir.join("guix.sigs"))
-            .args(["checkout", "-b", &branch_name])
+            .current_dir(&guix_sigs)
+            .args(["checkout", "-B", branch_name, base])
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
         self.run_command_with_output(command)?;
 
         // Add files
-        let add_files = if attestation_type == "all" {
-            vec![
-                format!("{}/{}/all.SHA256SUMS", &tag[1..], &self.config.signer_name),
-                format!(
-                    "{}/{}/all.SHA256SUMS.asc",
-                    &tag[1..],
-                    &self.config.signer_name
-                ),
-                format!(
-                    "{}/{}/noncodesigned.SHA256SUMS",
-                    &tag[1..],
-                    &self.config.signer_name
-                ),
-                format!(
-                    "{}/{}/noncodesigned.SHA256SUMS.asc",
-                    &tag[1..],
-                    &self.config.signer_name
-                ),
-            ]
-        } else {
-            vec![
-                format!(
-                    "{}/{}/noncodesigned.SHA256SUMS",
-                    &tag[1..],
-                    &self.config.signer_name
-                ),
-                format!(
-                    "{}/{}/noncodesigned.SHA256SUMS.asc",
-                    &tag[1..],
-                    &self.config.signer_name
-                ),
-            ]
-        };
-
         let mut git_add_args = vec!["add"];
         git_add_args.extend(add_files.iter().map(String::as_str));
 
         let mut command = Command::new("git");
         command
-            .current_dir(self.config.guix_build_dir.join("guix.sigs"))
+            .current_dir(&guix_sigs)
             .args(&git_add_args)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
@@ -584,91 +835,306 @@ impl Builder {
         // Echo the sigs
         let mut command = Command::new("cat");
         command
-            .current_dir(self.config.guix_build_dir.join("guix.sigs"))
+            .current_dir(&guix_sigs)
             .args(add_files.iter().map(String::as_str))
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
         self.run_command_with_output(command)?;
 
+        if self.git_succeeds(&["diff", "--cached", "--quiet"])? {
+            if base == "main" {
+                info!("Attestations are already on main, nothing to commit");
+                return Ok(false);
+            }
+            info!("Attestations are already on {}, nothing to commit", base);
+            return Ok(true);
+        }
+
+        if let Some(sha) = existing {
+            if self.git_succeeds(&["diff", "--cached", "--quiet", &sha])? {
+                info!(
+                    "Attestations are unchanged, keeping existing commit {}",
+                    sha
+                );
+                run_command(&guix_sigs, "git", &["reset", "--soft", &sha])?;
+                return Ok(true);
+            }
+        }
+
         // Commit changes
+        let mut commit_args = vec![
+            "commit".to_string(),
+            "-m".to_string(),
+            commit_message.to_string(),
+        ];
+        if self.config.sign_commits {
+            commit_args.push(format!("--gpg-sign={}", self.config.gpg_key_id));
+        }
+        let mut command = Command::new("git");
+        command
+            .current_dir(&guix_sigs)
+            .args(&commit_args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        self.run_command_with_output(command)?;
+        Ok(true)
+    }
+
+    /// Push the attestation branch and open a pull request in auto mode, or print instructions.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing true if the branch was pushed to an open pull request, or false if it
+    /// has to be pushed manually or no pull request could be opened.
+    async fn publish_attestations(
+        &self,
+        attestation_type: &str,
+        branch_name: &str,
+        commit_message: &str,
+        add_files: &[String],
+        forge: Option<&dyn Forge>,
+    ) -> Result<bool> {
+        if !self.args.auto {
+            warn!(
+                r#"Changes must be manually pushed to {} and a PR opened.
+To push the changes, run the following commands:
+    cd {:?}
+    git push --force-with-lease --set-upstream origin {}"#,
+                self.config.forge, &self.config.guix_sigs_dir, branch_name
+            );
+            return Ok(false);
+        }
+        let Some(forge) = forge else {
+            error!(
+                "{} API key ({}) not set in environment. Cannot create pull request.",
+                self.config.forge,
+                self.config.forge.token_name()
+            );
+            return Ok(false);
+        };
+
         let mut command = Command::new("git");
         command
             .current_dir(self.config.guix_build_dir.join("guix.sigs"))
-            .args(["commit", "-m", &commit_message])
+            // Replace our own earlier push of the branch, but not commits pushed from elsewhere
+            .args([
+                "push",
+                "--force-with-lease",
+                "--set-upstream",
+                "origin",
+                branch_name,
+            ])
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
         self.run_command_with_output(command)?;
-        if self.args.auto {
-            if let Some(octocrab) = build {
-                let mut command = Command::new("git");
-                command
-                    .current_dir(self.config.guix_build_dir.join("guix.sigs"))
-                    .args(["push", "--set-upstream", "origin", branch_name.as_str()])
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::piped());
-                self.run_command_with_output(command)?;
-
-                // Get the GitHub username
-                if let Some(github_user) = self
-                    .config
-                    .github_username
-                    .as_deref()
-                    .filter(|s| !s.is_empty())
-                {
-                    let pull_request = octocrab
-                        .pulls(
-                            self.config.guix_sigs_repo_owner.clone(),
-                            self.config.guix_sigs_repo_name.clone(),
-                        )
-                        .create(
-                            &commit_message,
-                            format!("{}:{}", github_user, branch_name),
-                            "main",
-                        )
-                        .body("Automatically created pull request for new attestations.")
-                        .send()
-                        .await?;
 
-                    info!("Pull request created: {}", pull_request.html_url.unwrap());
-                } else {
-                    error!("Valid GitHub username not available. Cannot create pull request as no github_username found in config.");
+        // Get the forge username
+        let Some(username) = self
+            .config
+            .github_username
+            .as_deref()
+            .filter(|s| !s.is_empty())
+        else {
+            error!("Valid {} username not available. Cannot create pull request as no github_username found in config.", self.config.forge);
+            return Ok(false);
+        };
+
+        let body = self.pull_request_body(attestation_type, add_files)?;
+        match forge.find_open_pull_request(username, branch_name).await? {
+            Some(pull_request) => {
+                if self.config.comment_on_pr_update {
+                    forge
+                        .comment(&pull_request, &format!("Updated: {}", commit_message))
+                        .await?;
                 }
-            } else {
-                error!(
-                    "GitHub API key ({}) not set in environment. Cannot create pull request.",
-                    GH_TOKEN_NAME
+                if pull_request.draft && attestation_type == "codesigned" {
+                    forge.mark_ready(&pull_request, &body).await?;
+                    info!(
+                        "Marked pull request #{} ready for review",
+                        pull_request.number
+                    );
+                }
+                info!(
+                    "Pull request already open, pushed update: {}",
+                    pull_request.url
                 );
             }
-        } else {
-            warn!(
-                r#"Changes must be manually pushed to GitHub and a PR opened.
-To push the changes, run the following commands:
-    cd {:?}
-    git push --set-upstream origin {}"#,
-                &self.config.guix_sigs_dir, branch_name
+            None => {
+                let draft = self.config.draft_pull_requests && attestation_type == "non-codesigned";
+                let pull_request = forge
+                    .create_pull_request(username, branch_name, commit_message, &body, draft)
+                    .await?;
+                if !self.config.pr_labels.is_empty() {
+                    forge
+                        .add_labels(&pull_request, &self.config.pr_labels)
+                        .await?;
+                }
+                if !self.config.pr_reviewers.is_empty() {
+                    forge
+                        .request_reviewers(&pull_request, &self.config.pr_reviewers)
+                        .await?;
+                }
+
+                info!(
+                    "{} created: {}",
+                    if draft {
+                        "Draft pull request"
+                    } else {
+                        "Pull request"
+                    },
+                    pull_request.url
+                );
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// The attestation files guix-attest wrote for `attestation_type`, relative to guix.sigs.
+    ///
+    /// Codesigned attestations include `all.SHA256SUMS` alongside the noncodesigned files, unless
+    /// only a subset of hosts was built.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the file paths, or an error if any expected file is missing.
+    fn attestation_files(
+        &self,
+        tag: &BitcoinVersion,
+        attestation_type: &str,
+    ) -> Result<Vec<String>> {
+        let mut names = vec!["noncodesigned.SHA256SUMS", "noncodesigned.SHA256SUMS.asc"];
+        if attestation_type == "codesigned" {
+            if self.is_partial_build() {
+                warn!(
+                    "Only hosts {:?} were built, so all.SHA256SUMS is incomplete and will not be committed",
+                    self.config.hosts
+                );
+            } else {
+                names.extend(["all.SHA256SUMS", "all.SHA256SUMS.asc"]);
+            }
+        }
+
+        let guix_sigs = self.config.guix_build_dir.join("guix.sigs");
+        let files: Vec<String> = names
+            .iter()
+            .map(|name| format!("{}/{}/{}", tag.dir_name(), self.config.signer_name, name))
+            .collect();
+        let missing: Vec<&str> = files
+            .iter()
+            .filter(|file| !guix_sigs.join(file).exists())
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            bail!(
+                "guix-attest did not write the expected {} attestations in {:?}. Missing: {}",
+                attestation_type,
+                guix_sigs,
+                missing.join(", ")
             );
         }
+        Ok(files)
+    }
 
-        Ok(())
+    /// Describe the attested artifacts, the build and how it compares with other signers.
+    fn pull_request_body(&self, attestation_type: &str, add_files: &[String]) -> Result<String> {
+        let tag = self
+            .args
+            .tag
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Tag not set"))?;
+        let guix_sigs = self.config.guix_build_dir.join("guix.sigs");
+        let mut body = format!(
+            "{} attestations by {} for {}.\n",
+            attestation_type, self.config.signer_name, tag
+        );
+
+        for file in add_files.iter().filter(|f| f.ends_with(".SHA256SUMS")) {
+            let path = guix_sigs.join(file);
+            let sums = parse_sha256sums(
+                &fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read attestations: {:?}", path))?,
+            )?;
+            body.push_str(&format!(
+                "\n### `{}`\n\n| Artifact | SHA256 |\n|---|---|\n",
+                file
+            ));
+            for (artifact, hash) in sums {
+                body.push_str(&format!("| `{}` | `{}` |\n", artifact, hash));
+            }
+        }
+
+        body.push_str("\n### Build\n\n");
+        match BuildInfo::load(&self.build_info_path()?)? {
+            Some(info) => body.push_str(&format!(
+                "- Host Guix version: {}\n- Pinned Guix commit: {}\n- Build host: {}\n- Build duration: {}\n",
+                info.guix_version,
+                info.guix_commit,
+                info.host,
+                info.duration()
+            )),
+            None => body.push_str("Build metadata was not recorded for this build.\n"),
+        }
+
+        let report = compare_attestations(
+            &self.config.guix_sigs_dir,
+            &tag.dir_name(),
+            &self.config.signer_name,
+            comparison_file_name(attestation_type),
+        )?;
+        body.push_str(&format!(
+            "\n### Comparison with other signers\n\n```\n{}```\n",
+            report
+        ));
+        Ok(body)
     }
 
-    fn run_command(&self, dir: &PathBuf, command: &str, args: &[&str]) -> Result<()> {
-        let status = Command::new(command)
-            .current_dir(dir)
+    /// Run a git command in guix.sigs and return its trimmed stdout, or None if it failed.
+    fn git_output(&self, args: &[&str]) -> Result<Option<String>> {
+        let output = Command::new("git")
+            .current_dir(self.config.guix_build_dir.join("guix.sigs"))
             .args(args)
-            .status()
-            .with_context(|| format!("Failed to execute command: {} {:?}", command, args))?;
-
-        if !status.success() {
-            bail!("Command failed: {} {:?}", command, args);
+            .output()
+            .with_context(|| format!("Failed to execute command: git {:?}", args))?;
+        if !output.status.success() {
+            return Ok(None);
         }
-        Ok(())
+        Ok(Some(
+            String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        ))
+    }
+
+    /// Run a git command in guix.sigs and return whether it exited successfully.
+    fn git_succeeds(&self, args: &[&str]) -> Result<bool> {
+        Ok(self.git_output(args)?.is_some())
     }
 
+    /// Run a build step, streaming its output to the terminal and saving it with timestamps to
+    /// `<guix_build_dir>/logs/<tag>/<step>.log`.
+    ///
+    /// If the step fails, the error includes its last lines of output.
     fn run_command_with_output(&self, mut command: Command) -> Result<()> {
+        if shutdown_requested() {
+            bail!("Shutdown requested, not running command: {:?}", command);
+        }
+        if child_groups_managed() {
+            command.process_group(0);
+        }
+        let log_dir = self.config.guix_build_dir.join("logs").join(
+            self.args
+                .tag
+                .as_ref()
+                .map_or("master".to_string(), |tag| tag.to_string()),
+        );
+        let log = Arc::new(Mutex::new(StepLog::open(&log_dir, &step_name(&command))));
+        log.lock()
+            .expect("step log lock poisoned")
+            .record(&format!("$ {:?}", command));
+
         let mut child = command
             .spawn()
             .with_context(|| format!("Failed to execute command: {:?}", command))?;
+        let _group = register_group(child.id());
 
         let stdout = child.stdout.take().context("Failed to capture stdout")?;
         let stderr = child.stderr.take().context("Failed to capture stderr")?;
@@ -676,18 +1142,28 @@ To push the changes, run the following commands:
         let stdout_reader = BufReader::new(stdout);
         let stderr_reader = BufReader::new(stderr);
 
+        let stdout_log = log.clone();
         let stdout_handle = std::thread::spawn(move || {
             stdout_reader.lines().for_each(|line| {
                 if let Ok(line) = line {
                     println!("{}", line);
+                    stdout_log
+                        .lock()
+                        .expect("step log lock poisoned")
+                        .record(&line);
                 }
             });
         });
 
+        let stderr_log = log.clone();
         let stderr_handle = std::thread::spawn(move || {
             stderr_reader.lines().for_each(|line| {
                 if let Ok(line) = line {
                     eprintln!("{}", line);
+                    stderr_log
+                        .lock()
+                        .expect("step log lock poisoned")
+                        .record(&line);
                 }
             });
         });
@@ -698,9 +1174,88 @@ To push the changes, run the following commands:
         stderr_handle.join().expect("Stderr thread panicked");
 
         if !status.success() {
-            bail!("Command failed: {:?}", command);
+            let log = log.lock().expect("step log lock poisoned");
+            bail!(
+                "Command failed: {:?}\nLast lines of output (full log at {:?}):\n{}",
+                command,
+                log.path(),
+                log.tail()
+            );
         }
 
         Ok(())
     }
 }
+
+/// Name of the log file of a build step, e.g. "guix-build" or "git-commit".
+fn step_name(command: &Command) -> String {
+    let program = Path::new(command.get_program())
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    match command.get_args().next() {
+        Some(subcommand) if program == "git" => format!("git-{}", subcommand.to_string_lossy()),
+        _ => program,
+    }
+}
+
+/// The SHA256SUMS file compared with other signers for an attestation type.
+fn comparison_file_name(attestation_type: &str) -> &'static str {
+    if attestation_type == "codesigned" {
+        "all.SHA256SUMS"
+    } else {
+        "noncodesigned.SHA256SUMS"
+    }
+}
+
+/// Clone guix.sigs into `guix_sigs_dir` if it doesn't exist, with the fork as its origin.
+pub fn clone_guix_sigs(config: &Config) -> Result<()> {
+    if !config.guix_sigs_dir.exists() {
+        fs::create_dir_all(&config.guix_build_dir).context("Failed to create guix_build_dir")?;
+        info!("Cloning guix.sigs repository");
+        run_command(
+            &config.guix_build_dir,
+            "git",
+            &[
+                "clone",
+                "--origin",
+                "upstream",
+                upstream_url(
+                    &config.guix_sigs_fork_url,
+                    &config.guix_sigs_repo_owner,
+                    &config.guix_sigs_repo_name,
+                )
+                .as_str(),
+                config.guix_sigs_dir.file_name().unwrap().to_str().unwrap(),
+            ],
+        )
+        .context("Failed to clone guix.sigs repository")?;
+
+        // Set the origin remote
+        run_command(
+            &config.guix_sigs_dir,
+            "git",
+            &["remote", "add", "origin", &config.guix_sigs_fork_url],
+        )?;
+
+        info!(
+            "Set origin remote of the guix sigs repo to: {}",
+            &config.guix_sigs_fork_url
+        );
+    }
+    Ok(())
+}
+
+fn run_command(dir: &Path, command: &str, args: &[&str]) -> Result<()> {
+    let status = Command::new(command)
+        .current_dir(dir)
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to execute command: {} {:?}", command, args))?;
+
+    if !status.success() {
+        bail!("Command failed: {} {:?}", command, args);
+    }
+    Ok(())
+}
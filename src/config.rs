@@ -3,6 +3,11 @@ use dirs::{config_dir, state_dir};
 use std::fmt;
 use std::{path::PathBuf, time::Duration};
 
+use crate::filter::TagFilter;
+use crate::forge::ForgeKind;
+use crate::logging::LogConfig;
+use crate::signing::SignerConfig;
+
 pub static GH_TOKEN_NAME: &str = "GH_API_TOKEN";
 
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
@@ -13,17 +18,49 @@ pub struct Config {
     pub guix_sigs_repo_name: String,
     pub detached_repo_owner: String,
     pub detached_repo_name: String,
+    pub source_repo_remote: Option<String>,
+    pub detached_repo_remote: Option<String>,
     pub poll_interval: Duration,
+    #[serde(default = "default_stop_timeout")]
+    pub stop_timeout: Duration,
     pub signer_name: String,
     pub gpg_key_id: String,
+    #[serde(default)]
+    pub sign_commits: bool,
+    pub notify_command: Option<String>,
     pub guix_sigs_fork_url: String,
+    #[serde(default)]
+    pub forge: ForgeKind,
+    pub forge_url: Option<String>,
     pub multi_package: bool,
+    #[serde(default)]
+    pub hosts: Vec<String>,
     pub guix_build_dir: PathBuf,
     pub guix_sigs_dir: PathBuf,
     pub bitcoin_detached_sigs_dir: PathBuf,
     pub macos_sdks_dir: PathBuf,
     pub bitcoin_dir: PathBuf,
     pub github_username: Option<String>,
+    #[serde(default)]
+    pub comment_on_pr_update: bool,
+    #[serde(default)]
+    pub draft_pull_requests: bool,
+    #[serde(default)]
+    pub aggregate_pull_requests: bool,
+    #[serde(default)]
+    pub pr_labels: Vec<String>,
+    #[serde(default)]
+    pub pr_reviewers: Vec<String>,
+    #[serde(default)]
+    pub signer: SignerConfig,
+    #[serde(default)]
+    pub log: LogConfig,
+    #[serde(default)]
+    pub tag_filter: TagFilter,
+}
+
+fn default_stop_timeout() -> Duration {
+    Duration::from_secs(60)
 }
 
 impl Default for Config {
@@ -37,17 +74,33 @@ impl Default for Config {
             guix_sigs_repo_name: "guix.sigs".to_string(),
             detached_repo_owner: "bitcoin-core".to_string(),
             detached_repo_name: "bitcoin-detached-sigs".to_string(),
+            source_repo_remote: None,
+            detached_repo_remote: None,
             poll_interval: Duration::from_secs(300),
+            stop_timeout: default_stop_timeout(),
             signer_name: String::new(),
             gpg_key_id: String::new(),
+            sign_commits: false,
+            notify_command: None,
             guix_sigs_fork_url: String::new(),
+            forge: ForgeKind::default(),
+            forge_url: None,
             multi_package: false,
+            hosts: Vec::new(),
             guix_build_dir: guix_build_dir.clone(),
             guix_sigs_dir: guix_build_dir.join("guix.sigs"),
             bitcoin_detached_sigs_dir: guix_build_dir.join("bitcoin-detached-sigs"),
             macos_sdks_dir: guix_build_dir.join("macos-sdks"),
             bitcoin_dir: guix_build_dir.join("bitcoin"),
             github_username: None,
+            comment_on_pr_update: false,
+            draft_pull_requests: false,
+            aggregate_pull_requests: false,
+            pr_labels: Vec::new(),
+            pr_reviewers: Vec::new(),
+            signer: SignerConfig::default(),
+            log: LogConfig::default(),
+            tag_filter: TagFilter::default(),
         }
     }
 }
@@ -75,17 +128,32 @@ impl fmt::Display for Config {
         writeln!(f, "{:<32} {}/{}", "Source Repo:", self.source_repo_owner, self.source_repo_name)?;
         writeln!(f, "{:<32} {}/{}", "Guix sigs repo:", self.guix_sigs_repo_owner, self.guix_sigs_repo_name)?;
         writeln!(f, "{:<32} {}/{}", "Detached sigs repo:", self.detached_repo_owner, self.detached_repo_name)?;
+        writeln!(f, "{:<32} {}",    "Source Repo Tag Remote:", self.source_repo_remote.as_deref().unwrap_or("GitHub API"))?;
+        writeln!(f, "{:<32} {}",    "Detached Sigs Tag Remote:", self.detached_repo_remote.as_deref().unwrap_or("GitHub API"))?;
         writeln!(f, "{:<32} {:?}",  "Poll Interval:", self.poll_interval)?;
+        writeln!(f, "{:<32} {:?}",  "Stop Timeout:", self.stop_timeout)?;
         writeln!(f, "{:<32} {}",    "Signer Name:", self.signer_name)?;
         writeln!(f, "{:<32} {}",    "GPG Key Short ID:", self.gpg_key_id)?;
+        writeln!(f, "{:<32} {}",    "Sign Commits:", self.sign_commits)?;
+        writeln!(f, "{:<32} {}",    "Notify Command:", self.notify_command.as_deref().unwrap_or("None"))?;
         writeln!(f, "{:<32} {}",    "Guix Sigs Fork URL:", self.guix_sigs_fork_url)?;
+        writeln!(f, "{:<32} {}",    "Forge:", self.forge)?;
+        writeln!(f, "{:<32} {}",    "Forge API URL:", self.forge_url.as_deref().unwrap_or("default"))?;
         writeln!(f, "{:<32} {}",    "Multi-package:", self.multi_package)?;
+        writeln!(f, "{:<32} {}",    "Hosts:", if self.hosts.is_empty() { "all".to_string() } else { self.hosts.join(" ") })?;
         writeln!(f, "{:<32} {:?}",  "Guix Build Directory:", self.guix_build_dir)?;
         writeln!(f, "{:<32} {:?}",  "Guix Sigs Directory:", self.guix_sigs_dir)?;
         writeln!(f, "{:<32} {:?}",  "Bitcoin Detached Sigs Directory:", self.bitcoin_detached_sigs_dir)?;
         writeln!(f, "{:<32} {:?}",  "macOS SDKs Directory:", self.macos_sdks_dir)?;
         writeln!(f, "{:<32} {:?}",  "Bitcoin Directory:", self.bitcoin_dir)?;
         writeln!(f, "{:<32} {}",    "GitHub Username:", self.github_username.as_deref().unwrap_or("None"))?;
+        writeln!(f, "{:<32} {}",    "Comment On PR Update:", self.comment_on_pr_update)?;
+        writeln!(f, "{:<32} {}",    "Draft Pull Requests:", self.draft_pull_requests)?;
+        writeln!(f, "{:<32} {}",    "Aggregate Pull Requests:", self.aggregate_pull_requests)?;
+        writeln!(f, "{:<32} {:?}",  "PR Labels:", self.pr_labels)?;
+        writeln!(f, "{:<32} {:?}",  "PR Reviewers:", self.pr_reviewers)?;
+        writeln!(f, "{:<32} {}",    "Watch Log:", self.log)?;
+        writeln!(f, "{:<32} {}",    "Tag Filter:", self.tag_filter)?;
         writeln!(f, "{:<32} {}",    "GitHub Token:", if self.get_github_token().is_some() { "[set in environment]" } else { "Not set" })?;
         Ok(())
     }
@@ -103,3 +171,21 @@ pub(crate) fn read_config() -> Result<Config> {
     Config::load()
         .context("Failed to load config. Please run 'bgt setup' to set up your configuration.")
 }
+
+/// Settings given on the command line that take precedence over the config file.
+#[derive(Clone, Default)]
+pub(crate) struct ConfigOverrides {
+    pub multi_package: bool,
+    pub hosts: Vec<String>,
+}
+
+impl ConfigOverrides {
+    pub fn apply(&self, config: &mut Config) {
+        if self.multi_package {
+            config.multi_package = true;
+        }
+        if !self.hosts.is_empty() {
+            config.hosts = self.hosts.clone();
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use anyhow::{bail, Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream as AsyncUnixStream};
+use tokio::sync::Notify;
+use tokio::time::timeout;
+
+use crate::config::{read_config, Config, ConfigOverrides};
+use crate::process::cancel_running_group;
+use crate::version::BitcoinVersion;
+
+/// A command sent to a running watcher over its control socket.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum ControlCommand {
+    Poll,
+    Enqueue { tag: String },
+    Pause,
+    Resume,
+    Cancel,
+    Reload,
+}
+
+/// How long a client has to send its command before the connection is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The largest command accepted, well above the size of any valid command.
+const MAX_REQUEST_SIZE: u64 = 64 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ControlResponse {
+    ok: bool,
+    message: String,
+}
+
+/// Requests received over the control socket, shared between the socket task and the watcher
+/// loop.
+///
+/// Build steps block the watcher loop, so cancelling is done directly by the socket task while
+/// everything else is picked up by the loop once the running job has finished.
+#[derive(Default)]
+pub struct Control {
+    paused: AtomicBool,
+    poll_requested: AtomicBool,
+    queue: Mutex<VecDeque<String>>,
+    reloaded: Mutex<Option<Config>>,
+    stop_timeout: Mutex<Duration>,
+    wake: Notify,
+}
+
+impl Control {
+    pub fn new(stop_timeout: Duration) -> Self {
+        Self {
+            stop_timeout: Mutex::new(stop_timeout),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Ask the watcher loop to poll for new tags as soon as it is idle.
+    pub fn request_poll(&self) {
+        self.poll_requested.store(true, Ordering::SeqCst);
+        self.wake.notify_one();
+    }
+
+    pub fn take_poll_request(&self) -> bool {
+        self.poll_requested.swap(false, Ordering::SeqCst)
+    }
+
+    /// Add a tag to the queue of tags to process.
+    ///
+    /// # Returns
+    ///
+    /// False if the tag was already queued.
+    pub fn enqueue(&self, tag: &str) -> bool {
+        let mut queue = self.queue.lock().expect("control queue lock poisoned");
+        if queue.iter().any(|t| t == tag) {
+            return false;
+        }
+        queue.push_back(tag.to_string());
+        self.wake.notify_one();
+        true
+    }
+
+    pub fn next_queued(&self) -> Option<String> {
+        self.queue
+            .lock()
+            .expect("control queue lock poisoned")
+            .pop_front()
+    }
+
+    pub fn queued(&self) -> Vec<String> {
+        self.queue
+            .lock()
+            .expect("control queue lock poisoned")
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Take the config loaded by a reload command, if one is waiting to be applied.
+    pub fn take_reloaded(&self) -> Option<Config> {
+        self.reloaded
+            .lock()
+            .expect("control config lock poisoned")
+            .take()
+    }
+
+    /// How long a cancelled or stopped job gets to exit before it is killed, as of the last
+    /// reload.
+    pub fn stop_timeout(&self) -> Duration {
+        *self
+            .stop_timeout
+            .lock()
+            .expect("control stop timeout lock poisoned")
+    }
+
+    /// Wait until a command needs the attention of the watcher loop.
+    pub async fn notified(&self) {
+        self.wake.notified().await
+    }
+
+    fn handle(&self, command: ControlCommand, overrides: &ConfigOverrides) -> Result<String> {
+        let waiting = if self.is_paused() {
+            ", it will run once the watcher is resumed"
+        } else {
+            ""
+        };
+        match command {
+            ControlCommand::Poll => {
+                self.request_poll();
+                Ok(format!("Poll requested{}", waiting))
+            }
+            ControlCommand::Enqueue { tag } => {
+                let version: BitcoinVersion = tag.parse()?;
+                if self.enqueue(&version.to_string()) {
+                    Ok(format!("Queued tag {}{}", version, waiting))
+                } else {
+                    Ok(format!("Tag {} is already queued", version))
+                }
+            }
+            ControlCommand::Pause => {
+                if self.paused.swap(true, Ordering::SeqCst) {
+                    return Ok("Watcher is already paused".to_string());
+                }
+                self.wake.notify_one();
+                Ok("Watcher paused, a running job will still finish".to_string())
+            }
+            ControlCommand::Resume => {
+                if !self.paused.swap(false, Ordering::SeqCst) {
+                    return Ok("Watcher is not paused".to_string());
+                }
+                self.wake.notify_one();
+                Ok("Watcher resumed".to_string())
+            }
+            ControlCommand::Cancel => match cancel_running_group(self.stop_timeout())? {
+                Some(pgid) => Ok(format!("Cancelled running job (process group {})", pgid)),
+                None => bail!("No job is running"),
+            },
+            ControlCommand::Reload => {
+                let mut config = read_config()?;
+                overrides.apply(&mut config);
+                *self
+                    .stop_timeout
+                    .lock()
+                    .expect("control stop timeout lock poisoned") = config.stop_timeout;
+                *self.reloaded.lock().expect("control config lock poisoned") = Some(config);
+                self.wake.notify_one();
+                Ok("Config reloaded, it applies from the next job".to_string())
+            }
+        }
+    }
+}
+
+/// Listen for control commands on a Unix socket at `path`, replacing a stale socket left by a
+/// watcher that is no longer running.
+///
+/// Each connection is handled on its own task, so a client that stalls can't block others.
+pub fn spawn_control_server(
+    path: &Path,
+    control: Arc<Control>,
+    overrides: ConfigOverrides,
+) -> Result<()> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            bail!("Another watcher is already listening on {:?}", path);
+        }
+        fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale control socket: {:?}", path))?;
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind control socket: {:?}", path))?;
+    info!("Listening for control commands on {:?}", path);
+    let overrides = Arc::new(overrides);
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!("Failed to accept control connection: {}", e);
+                    continue;
+                }
+            };
+            let control = control.clone();
+            let overrides = overrides.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(stream, &control, &overrides).await {
+                    warn!("Failed to handle control connection: {:#}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Read a command from `stream`, run it and write back the response.
+async fn handle_connection(
+    mut stream: AsyncUnixStream,
+    control: &Control,
+    overrides: &ConfigOverrides,
+) -> Result<()> {
+    let mut request = String::new();
+    timeout(
+        REQUEST_TIMEOUT,
+        (&mut stream)
+            .take(MAX_REQUEST_SIZE + 1)
+            .read_to_string(&mut request),
+    )
+    .await
+    .context("Timed out reading control command")?
+    .context("Failed to read control command")?;
+    let result = if request.len() as u64 > MAX_REQUEST_SIZE {
+        Err(anyhow::anyhow!(
+            "Control command is larger than {} bytes",
+            MAX_REQUEST_SIZE
+        ))
+    } else {
+        serde_json::from_str::<ControlCommand>(&request)
+            .context("Invalid control command")
+            .and_then(|command| {
+                debug!("Received control command: {:?}", command);
+                control.handle(command, overrides)
+            })
+    };
+    let response = match result {
+        Ok(message) => {
+            info!("{}", message);
+            ControlResponse { ok: true, message }
+        }
+        Err(e) => ControlResponse {
+            ok: false,
+            message: format!("{:#}", e),
+        },
+    };
+    let response = serde_json::to_vec(&response).expect("response serializes");
+    stream
+        .write_all(&response)
+        .await
+        .context("Failed to write control response")
+}
+
+/// Send a command to the watcher listening on `path`.
+///
+/// # Returns
+///
+/// A Result containing the watcher's reply, or an error if the watcher rejected the command.
+pub fn send_command(path: &Path, command: &ControlCommand) -> Result<String> {
+    let mut stream = UnixStream::connect(path).with_context(|| {
+        format!(
+            "Failed to connect to watcher control socket {:?}. Is the watcher running?",
+            path
+        )
+    })?;
+    let request = serde_json::to_vec(command).context("Failed to serialize control command")?;
+    stream
+        .write_all(&request)
+        .and_then(|_| stream.shutdown(Shutdown::Write))
+        .context("Failed to send control command")?;
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .context("Failed to read control response")?;
+    let response: ControlResponse =
+        serde_json::from_str(&response).context("Failed to parse control response")?;
+    if !response.ok {
+        bail!("{}", response.message);
+    }
+    Ok(response.message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_control_socket() {
+        let path = std::env::temp_dir().join(format!("bgt-control-{}.sock", std::process::id()));
+        let control = Arc::new(Control::new(Duration::from_secs(1)));
+        spawn_control_server(&path, control.clone(), ConfigOverrides::default()).unwrap();
+        assert!(spawn_control_server(&path, control.clone(), ConfigOverrides::default()).is_err());
+
+        // A client that never finishes its command doesn't hold up the others
+        let _stalled = UnixStream::connect(&path).unwrap();
+
+        let send = |command: ControlCommand| {
+            let path = path.clone();
+            tokio::task::spawn_blocking(move || send_command(&path, &command))
+        };
+        send(ControlCommand::Pause).await.unwrap().unwrap();
+        assert!(control.is_paused());
+        let tag = |tag: &str| ControlCommand::Enqueue {
+            tag: tag.to_string(),
+        };
+        send(tag("28.0")).await.unwrap().unwrap();
+        send(tag("v28.0")).await.unwrap().unwrap();
+        assert!(send(tag("latest")).await.unwrap().is_err());
+        assert!(send(ControlCommand::Cancel).await.unwrap().is_err());
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(control.queued(), vec!["v28.0"]);
+        assert!(!control.take_poll_request());
+    }
+}
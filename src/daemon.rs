@@ -1,14 +1,34 @@
 use anyhow::{Context, Result};
 use daemonize::Daemonize;
-use log::{error, info};
-use std::fs::File;
+use log::{error, info, warn};
+use std::fs::OpenOptions;
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
-pub fn start_daemon(pid_file: &PathBuf, log_file: &PathBuf) -> Result<()> {
-    let stdout = File::create(log_file)
-        .with_context(|| format!("Failed to create log file for stdout: {:?}", log_file))?;
-    let stderr = File::create(log_file)
-        .with_context(|| format!("Failed to create log file for stderr: {:?}", log_file))?;
+use crate::logging::{redirect_output, LogConfig, LogPipe};
+use crate::process::{is_alive, running_group_file};
+
+/// Extra time the watcher gets after the stop timeout to escalate to SIGKILL on its children.
+const STOP_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Daemonize the watcher, appending its output to `log_file`, which is rotated as configured.
+///
+/// # Returns
+///
+/// A Result containing the pipe the daemon's output goes through, to be finished before exiting.
+pub fn start_daemon(
+    pid_file: &PathBuf,
+    log_file: &PathBuf,
+    log_config: &LogConfig,
+) -> Result<LogPipe> {
+    let stdout = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)
+        .with_context(|| format!("Failed to open log file: {:?}", log_file))?;
+    let stderr = stdout
+        .try_clone()
+        .with_context(|| format!("Failed to share log file with stderr: {:?}", log_file))?;
 
     let daemonize = Daemonize::new()
         .pid_file(pid_file)
@@ -19,8 +39,10 @@ pub fn start_daemon(pid_file: &PathBuf, log_file: &PathBuf) -> Result<()> {
 
     match daemonize.start() {
         Ok(_) => {
+            let log_pipe = redirect_output(log_file, log_config.clone())
+                .context("Failed to set up log rotation")?;
             info!("Daemon started successfully.");
-            Ok(())
+            Ok(log_pipe)
         }
         Err(e) => {
             error!("Error starting daemon: {}", e);
@@ -29,7 +51,34 @@ pub fn start_daemon(pid_file: &PathBuf, log_file: &PathBuf) -> Result<()> {
     }
 }
 
-pub fn stop_daemon(pid_file: &PathBuf) -> Result<()> {
+/// Send SIGKILL to the process group of the build step the watcher was running, if any.
+fn kill_running_group() {
+    let path = running_group_file();
+    let Some(pgid) = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| s.trim().parse::<i32>().ok())
+    else {
+        return;
+    };
+    warn!("Sending SIGKILL to build process group {}", pgid);
+    unsafe {
+        if libc::kill(-pgid, libc::SIGKILL) == -1 {
+            warn!(
+                "Failed to send SIGKILL to process group {}: {}",
+                pgid,
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+    let _ = std::fs::remove_file(&path);
+}
+
+/// Stop the watcher daemon, giving it `timeout` to shut down before killing it.
+///
+/// The watcher is sent SIGTERM, which makes it cancel the running build step. If it has not
+/// exited after `timeout` (plus a grace period for its own cleanup), its process group and the
+/// process group of the running build step are sent SIGKILL.
+pub fn stop_daemon(pid_file: &PathBuf, timeout: Duration) -> Result<()> {
     if pid_file.exists() {
         let pid = std::fs::read_to_string(pid_file)
             .with_context(|| format!("Failed to read PID from file: {:?}", pid_file))?
@@ -37,10 +86,35 @@ pub fn stop_daemon(pid_file: &PathBuf) -> Result<()> {
             .parse::<i32>()
             .context("Failed to parse PID as integer")?;
 
-        unsafe {
-            if libc::kill(pid, libc::SIGKILL) == -1 {
-                return Err(std::io::Error::last_os_error())
-                    .context("Failed to send SIGKILL to daemon process");
+        if !is_alive(pid) {
+            println!("Daemon is not running (stale PID file).");
+        } else {
+            unsafe {
+                if libc::kill(pid, libc::SIGTERM) == -1 {
+                    return Err(std::io::Error::last_os_error())
+                        .context("Failed to send SIGTERM to daemon process");
+                }
+            }
+
+            let deadline = Instant::now() + timeout + STOP_GRACE_PERIOD;
+            while is_alive(pid) && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(500));
+            }
+
+            if is_alive(pid) {
+                warn!(
+                    "Daemon did not stop within {:?}, sending SIGKILL",
+                    timeout + STOP_GRACE_PERIOD
+                );
+                // daemonize makes the daemon the leader of its own process group, but each
+                // build step runs in a group of its own, which is published to a file
+                kill_running_group();
+                unsafe {
+                    if libc::kill(-pid, libc::SIGKILL) == -1 {
+                        return Err(std::io::Error::last_os_error())
+                            .context("Failed to send SIGKILL to daemon process");
+                    }
+                }
             }
         }
 
@@ -1,77 +1,222 @@
-use anyhow::{Context, Result};
-use log::{debug, info};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use http::header::{HeaderMap, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH};
+use log::{debug, info, warn};
+use octocrab::Octocrab;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::fs::{File, OpenOptions};
+use std::future::Future;
 use std::io::{BufRead, BufReader, Write};
 use std::path::PathBuf;
 use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::config::{get_config_file, Config};
-use crate::version::compare_versions;
+use crate::version::compare_tags;
 
-/// Fetches all tags from the GitHub repository and updates the known tags file.
+const GITHUB_API_URL: &str = "https://api.github.com";
+
+/// Cache validators and contents of one page of a tags listing, used to make conditional
+/// requests.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct PageCache {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    tags: Vec<String>,
+    next: Option<String>,
+}
+
+/// The pages of the last tags listing, keyed by page URL.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TagsCache {
+    #[serde(default)]
+    pages: BTreeMap<String, PageCache>,
+}
+
+/// A page of a tags listing returned by the API.
+enum TagsPage {
+    NotModified,
+    Modified(PageCache),
+}
+
+/// A backend that can list the tags of a repository.
+#[async_trait]
+pub trait TagSource: Send + Sync {
+    /// A human readable description of the repository, used in log messages.
+    fn describe(&self) -> String;
+
+    /// Fetches the names of all tags in the repository.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing a Vector of tag names, None if the tags are known to be unchanged
+    /// since the last fetch, or an error if the fetch failed.
+    async fn fetch_tags(&self) -> Result<Option<Vec<String>>>;
+
+    /// Forgets any state cached between fetches, so the next fetch returns every tag.
+    fn reset(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The tag sources for the bitcoin and detached sigs repositories.
+pub struct TagSources {
+    pub bitcoin: Box<dyn TagSource>,
+    pub sigs: Box<dyn TagSource>,
+}
+
+impl TagSources {
+    /// Creates tag sources for both repositories, using `git ls-remote` for a repository when
+    /// a remote is configured for it and the GitHub API otherwise.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let octo = github_client(config)?;
+        let source = |remote: &Option<String>, owner: &str, name: &str, repo_type: &str| {
+            let source: Box<dyn TagSource> = match remote.as_deref().filter(|s| !s.is_empty()) {
+                Some(remote) => Box::new(GitRemoteTagSource::new(remote)),
+                None => Box::new(GitHubTagSource::new(octo.clone(), owner, name, repo_type)),
+            };
+            source
+        };
+        Ok(Self {
+            bitcoin: source(
+                &config.source_repo_remote,
+                &config.source_repo_owner,
+                &config.source_repo_name,
+                "bitcoin",
+            ),
+            sigs: source(
+                &config.detached_repo_remote,
+                &config.detached_repo_owner,
+                &config.detached_repo_name,
+                "sigs",
+            ),
+        })
+    }
+}
+
+/// Lists tags through the GitHub API, using conditional requests between fetches.
+pub struct GitHubTagSource {
+    octo: Octocrab,
+    owner: String,
+    name: String,
+    cache_path: PathBuf,
+}
+
+impl GitHubTagSource {
+    pub fn new(octo: Octocrab, owner: &str, name: &str, repo_type: &str) -> Self {
+        Self {
+            octo,
+            owner: owner.to_string(),
+            name: name.to_string(),
+            cache_path: get_config_file(&format!("known_tags_{}.cache", repo_type)),
+        }
+    }
+}
+
+#[async_trait]
+impl TagSource for GitHubTagSource {
+    fn describe(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+
+    async fn fetch_tags(&self) -> Result<Option<Vec<String>>> {
+        let mut cache = read_tags_cache(&self.cache_path);
+        let tags = fetch_remote_tags(&self.octo, &self.owner, &self.name, &mut cache).await?;
+        write_tags_cache(&cache, &self.cache_path)?;
+        Ok(tags)
+    }
+
+    fn reset(&self) -> Result<()> {
+        write_tags_cache(&TagsCache::default(), &self.cache_path)
+    }
+}
+
+/// Lists tags of any git remote (URL or local path) with `git ls-remote`.
+pub struct GitRemoteTagSource {
+    remote: String,
+}
+
+impl GitRemoteTagSource {
+    pub fn new(remote: &str) -> Self {
+        Self {
+            remote: remote.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl TagSource for GitRemoteTagSource {
+    fn describe(&self) -> String {
+        self.remote.clone()
+    }
+
+    async fn fetch_tags(&self) -> Result<Option<Vec<String>>> {
+        let output = Command::new("git")
+            .args(["ls-remote", "--tags", "--refs", &self.remote])
+            .output()
+            .context("Failed to execute git ls-remote command")?;
+
+        if !output.status.success() {
+            bail!(
+                "git ls-remote failed for {}: {}",
+                self.remote,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(Some(parse_ls_remote(&String::from_utf8_lossy(
+            &output.stdout,
+        ))))
+    }
+}
+
+/// Parses the output of `git ls-remote --tags --refs` into tag names.
+fn parse_ls_remote(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .filter_map(|r| r.strip_prefix("refs/tags/"))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Fetches all tags from both tag sources and updates the known tags files.
 ///
 /// # Returns
 ///
 /// A Result tuple of HashSets of all known tags for each of the two repos, or an error if the fetch failed.
-pub async fn fetch_all_tags(config: &Config) -> Result<(HashSet<String>, HashSet<String>)> {
+pub async fn fetch_all_tags(sources: &TagSources) -> Result<(HashSet<String>, HashSet<String>)> {
     let mut bitcoin_tags = HashSet::new();
     let mut sig_tags = HashSet::new();
 
-    for (repo_type, owner, name, tags_file, tag_set) in [
-        (
-            "bitcoin",
-            &config.source_repo_owner,
-            &config.source_repo_name,
-            "known_tags_bitcoin",
-            &mut bitcoin_tags,
-        ),
-        (
-            "sigs",
-            &config.detached_repo_owner,
-            &config.detached_repo_name,
-            "known_tags_sigs",
-            &mut sig_tags,
-        ),
+    for (repo_type, source, tag_set) in [
+        ("bitcoin", &sources.bitcoin, &mut bitcoin_tags),
+        ("sigs", &sources.sigs, &mut sig_tags),
     ] {
         info!("Processing {} repository", repo_type);
 
         info!("Reading existing known tags from file...");
-        let path = get_config_file(tags_file);
+        let path = get_config_file(&format!("known_tags_{}", repo_type));
         let mut existing_tags = read_known_tags(&path).unwrap_or_else(|_| {
             info!("No existing tags file found, starting fresh.");
             HashSet::new()
         });
+        if existing_tags.is_empty() {
+            source.reset()?;
+        }
         info!("Found {} existing tags", existing_tags.len());
 
-        info!("Fetching all tags from {}/{} repository...", owner, name);
-
-        let output = Command::new("curl")
-            .args([
-                "-H",
-                "User-Agent: BGT-Builder",
-                &format!(
-                    "https://api.github.com/repos/{}/{}/git/refs/tags",
-                    owner, name
-                ),
-            ])
-            .output()
-            .context("Failed to execute curl command")?;
+        info!("Fetching all tags from {} repository...", source.describe());
 
-        let tags: Vec<Value> = serde_json::from_slice(&output.stdout)
-            .context("Failed to parse JSON response from GitHub API")?;
+        let tags = source.fetch_tags().await?.unwrap_or_default();
 
         let mut new_tags = Vec::new();
-        for tag in &tags {
-            if let Some(ref_value) = tag.get("ref") {
-                if let Some(ref_str) = ref_value.as_str() {
-                    let tag_name = ref_str.trim_start_matches("refs/tags/").to_string();
-                    if existing_tags.insert(tag_name.clone()) {
-                        new_tags.push(tag_name.clone());
-                        tag_set.insert(tag_name);
-                    }
-                }
+        for tag_name in tags {
+            if existing_tags.insert(tag_name.clone()) {
+                new_tags.push(tag_name.clone());
+                tag_set.insert(tag_name);
             }
         }
 
@@ -109,63 +254,298 @@ pub async fn fetch_all_tags(config: &Config) -> Result<(HashSet<String>, HashSet
     );
 
     info!(
-        "Initialized with {} existing tags for {}/{}",
+        "Initialized with {} existing tags for {}",
         bitcoin_tags.len(),
-        &config.source_repo_owner,
-        &config.source_repo_name
+        sources.bitcoin.describe()
     );
     info!(
-        "Initialized with {} existing tags for {}/{}",
+        "Initialized with {} existing tags for {}",
         sig_tags.len(),
-        &config.detached_repo_owner,
-        &config.detached_repo_name
+        sources.sigs.describe()
     );
 
     Ok((bitcoin_tags, sig_tags))
 }
 
-/// Checks for new tags in the GitHub repository.
+/// Checks a tag source for new tags.
 ///
 /// # Returns
 ///
 /// A Result containing a Vector of new tags, or an error if the check failed.
 pub async fn check_for_new_tags(
+    source: &dyn TagSource,
     seen_tags: &mut HashSet<String>,
-    repo_owner: &str,
-    repo_name: &str,
+    repo_type: &str,
 ) -> Result<Vec<String>> {
-    let output = Command::new("curl")
-        .args([
-            "-H",
-            "User-Agent: BGT-Builder",
-            &format!(
-                "https://api.github.com/repos/{}/{}/git/refs/tags",
-                repo_owner, repo_name
-            ),
-        ])
-        .output()
-        .context("Failed to execute curl command to fetch tags")?;
-
-    let tags: Vec<Value> = serde_json::from_slice(&output.stdout)
-        .context("Failed to parse JSON response from GitHub API")?;
+    let Some(tags) = source.fetch_tags().await? else {
+        debug!("Tags for {} not modified", source.describe());
+        return Ok(Vec::new());
+    };
 
     info!("Fetched {} tags", tags.len());
     let mut new_tags = Vec::new();
-    for tag in tags {
-        let tag_name = tag["ref"]
-            .as_str()
-            .context("Failed to extract tag name from GitHub API response")?
-            .trim_start_matches("refs/tags/")
-            .to_string();
+    for tag_name in tags {
         if !seen_tags.contains(&tag_name) {
             info!("New tag detected: {}", tag_name);
             new_tags.push(tag_name.clone());
             seen_tags.insert(tag_name);
         }
     }
+    if !new_tags.is_empty() {
+        write_known_tags(
+            seen_tags,
+            &get_config_file(&format!("known_tags_{}", repo_type)),
+        )
+        .context("Failed to write updated known tags to file")?;
+    }
     Ok(new_tags)
 }
 
+/// Builds a GitHub API client, authenticated with the GitHub token when one is set.
+fn github_client(config: &Config) -> Result<Octocrab> {
+    let builder = Octocrab::builder();
+    let builder = match config.get_github_token().filter(|s| !s.is_empty()) {
+        Some(token) => builder.personal_token(token),
+        None => builder,
+    };
+    builder.build().context("Failed to build GitHub API client")
+}
+
+/// Fetches the names of all tags in a GitHub repository, following pagination.
+///
+/// Every page is requested conditionally using the validators stored for its URL in `cache`,
+/// which is updated from the responses. Tags are sorted by name, so new tags can appear on any
+/// page.
+///
+/// # Returns
+///
+/// A Result containing a Vector of tag names, None if no page has been modified since `cache`
+/// was stored, or an error if the fetch failed.
+async fn fetch_remote_tags(
+    octo: &Octocrab,
+    owner: &str,
+    name: &str,
+    cache: &mut TagsCache,
+) -> Result<Option<Vec<String>>> {
+    // Requests must use a path relative to the API root, as octocrab only attaches the
+    // token to requests without an authority.
+    let first = format!("/repos/{}/{}/git/refs/tags?per_page=100", owner, name);
+    collect_pages(first, cache, |url, headers| {
+        fetch_tags_page(octo, owner, name, url, headers)
+    })
+    .await
+}
+
+/// Follows a paginated tags listing from `first`, using the cached contents of pages that
+/// were not modified.
+///
+/// # Returns
+///
+/// A Result containing a Vector of tag names, None if every page was unchanged, or an error
+/// if fetching a page failed.
+async fn collect_pages<F, Fut>(
+    first: String,
+    cache: &mut TagsCache,
+    mut fetch_page: F,
+) -> Result<Option<Vec<String>>>
+where
+    F: FnMut(String, HeaderMap) -> Fut,
+    Fut: Future<Output = Result<TagsPage>>,
+{
+    let mut tags = Vec::new();
+    let mut pages = BTreeMap::new();
+    let mut modified = false;
+    let mut next = Some(first);
+
+    while let Some(url) = next.take() {
+        let cached = cache.pages.get(&url);
+        let headers = cached.map(conditional_headers).unwrap_or_default();
+        let page = match fetch_page(url.clone(), headers).await? {
+            TagsPage::NotModified => cached
+                .cloned()
+                .with_context(|| format!("Not Modified response for uncached page {}", url))?,
+            TagsPage::Modified(page) => {
+                modified = true;
+                page
+            }
+        };
+        tags.extend(page.tags.iter().cloned());
+        next = page.next.clone();
+        pages.insert(url, page);
+    }
+
+    // A listing that has lost pages has changed as well
+    modified |= pages.len() != cache.pages.len();
+    cache.pages = pages;
+    Ok(modified.then_some(tags))
+}
+
+/// Fetches one page of tags from the GitHub API.
+///
+/// Rate-limited requests are retried once the rate limit resets. Any other error response from
+/// the API is returned as an error.
+async fn fetch_tags_page(
+    octo: &Octocrab,
+    owner: &str,
+    name: &str,
+    url: String,
+    headers: HeaderMap,
+) -> Result<TagsPage> {
+    loop {
+        let response = octo
+            ._get_with_headers(url.as_str(), Some(headers.clone()))
+            .await
+            .with_context(|| format!("Failed to fetch tags from {}", url))?;
+        let status = response.status().as_u16();
+        let header = |key: &str| {
+            response
+                .headers()
+                .get(key)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+
+        if let Some(wait) = rate_limit_wait(
+            status,
+            header("x-ratelimit-remaining").as_deref(),
+            header("x-ratelimit-reset").as_deref(),
+            header("retry-after").as_deref(),
+        ) {
+            warn!(
+                "GitHub API rate limit exceeded for {}/{}. Retrying in {:?}",
+                owner, name, wait
+            );
+            tokio::time::sleep(wait).await;
+            continue;
+        }
+
+        if status == 304 {
+            return Ok(TagsPage::NotModified);
+        }
+        let etag = header("etag");
+        let last_modified = header("last-modified");
+        let link = header("link");
+        let body = octo
+            .body_to_string(response)
+            .await
+            .context("Failed to read response body from GitHub API")?;
+        if !(200..300).contains(&status) {
+            let message = serde_json::from_str::<Value>(&body)
+                .ok()
+                .and_then(|json| json["message"].as_str().map(str::to_string))
+                .unwrap_or(body);
+            bail!(
+                "GitHub API returned {} for {}/{}: {}",
+                status,
+                owner,
+                name,
+                message
+            );
+        }
+
+        let json: Value =
+            serde_json::from_str(&body).context("Failed to parse JSON response from GitHub API")?;
+        let refs = json
+            .as_array()
+            .context("Unexpected non-array response from GitHub API")?;
+        let mut tags = Vec::new();
+        for tag in refs {
+            let tag_name = tag["ref"]
+                .as_str()
+                .context("Failed to extract tag name from GitHub API response")?
+                .trim_start_matches("refs/tags/")
+                .to_string();
+            tags.push(tag_name);
+        }
+
+        return Ok(TagsPage::Modified(PageCache {
+            etag,
+            last_modified,
+            tags,
+            next: link
+                .as_deref()
+                .and_then(next_page_link)
+                .map(|url| url.trim_start_matches(GITHUB_API_URL).to_string()),
+        }));
+    }
+}
+
+/// Builds the conditional request headers for a cached page.
+fn conditional_headers(page: &PageCache) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Some(etag) = page
+        .etag
+        .as_deref()
+        .and_then(|v| HeaderValue::from_str(v).ok())
+    {
+        headers.insert(IF_NONE_MATCH, etag);
+    } else if let Some(last_modified) = page
+        .last_modified
+        .as_deref()
+        .and_then(|v| HeaderValue::from_str(v).ok())
+    {
+        headers.insert(IF_MODIFIED_SINCE, last_modified);
+    }
+    headers
+}
+
+/// Reads the stored tags cache from a file, returning an empty cache if none is stored.
+fn read_tags_cache(path: &PathBuf) -> TagsCache {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+/// Writes the tags cache to a file.
+fn write_tags_cache(cache: &TagsCache, path: &PathBuf) -> Result<()> {
+    let contents = serde_json::to_string(cache).context("Failed to serialize tags cache")?;
+    std::fs::write(path, contents).context("Failed to write tags cache to file")
+}
+
+/// Determines how long to wait before retrying a rate-limited GitHub API response.
+///
+/// # Returns
+///
+/// None if the response was not rate-limited, otherwise the Duration until the rate limit resets.
+fn rate_limit_wait(
+    status: u16,
+    remaining: Option<&str>,
+    reset: Option<&str>,
+    retry_after: Option<&str>,
+) -> Option<Duration> {
+    if status != 403 && status != 429 {
+        return None;
+    }
+    if let Some(secs) = retry_after.and_then(|s| s.parse::<u64>().ok()) {
+        return Some(Duration::from_secs(secs));
+    }
+    if remaining != Some("0") {
+        return None;
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let reset = reset
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(now + 60);
+    Some(Duration::from_secs(reset.saturating_sub(now) + 1))
+}
+
+/// Extracts the URL of the next page from a GitHub `Link` header.
+fn next_page_link(link: &str) -> Option<String> {
+    link.split(',').find_map(|part| {
+        let (url, rel) = part.split_once(';')?;
+        rel.contains("rel=\"next\"").then(|| {
+            url.trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string()
+        })
+    })
+}
+
 /// Reads known tags from a file.
 ///
 /// # Returns
@@ -199,10 +579,113 @@ fn write_known_tags(tags: &HashSet<String>, path: &PathBuf) -> Result<()> {
         .context("Failed to open file for writing known tags")?;
 
     let mut sorted_tags: Vec<_> = tags.iter().collect();
-    sorted_tags.sort_by(|a, b| compare_versions(a, b));
+    sorted_tags.sort_by(|a, b| compare_tags(a, b));
 
     for tag in sorted_tags {
         writeln!(file, "{}", tag).context("Failed to write tag to file")?;
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_page_link() {
+        let link = r#"<https://api.github.com/repositories/1/git/refs/tags?per_page=100&page=2>; rel="next", <https://api.github.com/repositories/1/git/refs/tags?per_page=100&page=5>; rel="last""#;
+        assert_eq!(
+            next_page_link(link).as_deref(),
+            Some("https://api.github.com/repositories/1/git/refs/tags?per_page=100&page=2")
+        );
+        let last = r#"<https://api.github.com/repositories/1/git/refs/tags?page=4>; rel="prev""#;
+        assert_eq!(next_page_link(last), None);
+    }
+
+    #[test]
+    fn test_rate_limit_wait() {
+        assert_eq!(rate_limit_wait(200, Some("0"), None, None), None);
+        assert_eq!(rate_limit_wait(403, Some("12"), None, None), None);
+        assert_eq!(
+            rate_limit_wait(429, None, None, Some("30")),
+            Some(Duration::from_secs(30))
+        );
+        assert!(rate_limit_wait(403, Some("0"), Some("0"), None).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_collect_pages_detects_change_on_last_page() {
+        // Page URL -> (etag, tags, next page)
+        let mut server: BTreeMap<String, (String, Vec<String>, Option<String>)> = BTreeMap::new();
+        let tags = |range: std::ops::Range<u32>| range.map(|i| format!("v0.{}.0", i)).collect();
+        server.insert("p1".into(), ("e1".into(), tags(0..100), Some("p2".into())));
+        server.insert("p2".into(), ("e2".into(), tags(100..150), None));
+
+        let fetch = |server: &BTreeMap<_, _>| {
+            let server: BTreeMap<String, (String, Vec<String>, Option<String>)> = server.clone();
+            move |url: String, headers: HeaderMap| {
+                let (etag, tags, next) = server[&url].clone();
+                let not_modified = headers
+                    .get(IF_NONE_MATCH)
+                    .is_some_and(|v| v.to_str().unwrap() == etag);
+                async move {
+                    Ok(if not_modified {
+                        TagsPage::NotModified
+                    } else {
+                        TagsPage::Modified(PageCache {
+                            etag: Some(etag),
+                            tags,
+                            next,
+                            ..Default::default()
+                        })
+                    })
+                }
+            }
+        };
+
+        let mut cache = TagsCache::default();
+        let all = collect_pages("p1".into(), &mut cache, fetch(&server))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(all.len(), 150);
+        let unchanged = collect_pages("p1".into(), &mut cache, fetch(&server)).await;
+        assert_eq!(unchanged.unwrap(), None);
+
+        server.get_mut("p2").unwrap().0 = "e2-new".into();
+        server.get_mut("p2").unwrap().1.push("v29.0".into());
+        let changed = collect_pages("p1".into(), &mut cache, fetch(&server))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(changed.len(), 151);
+        assert!(changed.contains(&"v29.0".to_string()));
+        assert!(changed.contains(&"v0.0.0".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_git_remote_tag_source() {
+        let dir = std::env::temp_dir().join(format!("bgt-ls-remote-{}", std::process::id()));
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .current_dir(&dir)
+                .args(["-c", "user.name=bgt", "-c", "user.email=bgt@example.com"])
+                .args(args)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {:?} failed", args);
+        };
+        std::fs::create_dir_all(&dir).unwrap();
+        git(&["init", "--quiet"]);
+        git(&["commit", "--quiet", "--allow-empty", "-m", "init"]);
+        git(&["tag", "v27.0"]);
+        git(&["tag", "-a", "v28.0rc1", "-m", "v28.0rc1"]);
+
+        let source = GitRemoteTagSource::new(dir.to_str().unwrap());
+        let mut tags = source.fetch_tags().await.unwrap().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        tags.sort();
+        assert_eq!(tags, vec!["v27.0", "v28.0rc1"]);
+    }
+}
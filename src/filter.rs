@@ -0,0 +1,141 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::version::BitcoinVersion;
+
+/// Rules selecting which new tags the watcher builds.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TagFilter {
+    pub min_version: Option<BitcoinVersion>,
+    pub max_version: Option<BitcoinVersion>,
+    pub include: Vec<TagPattern>,
+    pub exclude: Vec<TagPattern>,
+    pub allow_rc: bool,
+}
+
+impl Default for TagFilter {
+    fn default() -> Self {
+        Self {
+            min_version: Some("v21.0".parse().expect("valid version")),
+            max_version: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            allow_rc: true,
+        }
+    }
+}
+
+impl TagFilter {
+    /// Checks a tag against the filter rules.
+    ///
+    /// # Returns
+    ///
+    /// None if the tag should be built, or the reason it was filtered out.
+    pub fn filter_reason(&self, version: &BitcoinVersion) -> Option<String> {
+        if let Some(min) = &self.min_version {
+            if version < min {
+                return Some(format!("older than minimum version {}", min));
+            }
+        }
+        if let Some(max) = &self.max_version {
+            if version > max {
+                return Some(format!("newer than maximum version {}", max));
+            }
+        }
+        let tag = version.to_string();
+        if !self.allow_rc && version.is_rc() {
+            return Some("release candidates are not allowed".to_string());
+        }
+        if !self.include.is_empty() && !self.include.iter().any(|p| p.0.is_match(&tag)) {
+            return Some("does not match any include pattern".to_string());
+        }
+        self.exclude
+            .iter()
+            .find(|p| p.0.is_match(&tag))
+            .map(|pattern| format!("matches exclude pattern {:?}", pattern))
+    }
+}
+
+impl fmt::Display for TagFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "min {}, max {}, include {:?}, exclude {:?}, {} release candidates",
+            self.min_version
+                .as_ref()
+                .map_or("none".to_string(), |v| v.to_string()),
+            self.max_version
+                .as_ref()
+                .map_or("none".to_string(), |v| v.to_string()),
+            self.include,
+            self.exclude,
+            if self.allow_rc { "allow" } else { "skip" }
+        )
+    }
+}
+
+/// A tag filter regex, compiled when the config is loaded so an invalid one is rejected up front.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TagPattern(Regex);
+
+impl TryFrom<String> for TagPattern {
+    type Error = String;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        Regex::new(&pattern)
+            .map(TagPattern)
+            .map_err(|e| format!("invalid tag filter regex {:?}: {}", pattern, e))
+    }
+}
+
+impl From<TagPattern> for String {
+    fn from(pattern: TagPattern) -> Self {
+        pattern.0.as_str().to_string()
+    }
+}
+
+impl fmt::Debug for TagPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> BitcoinVersion {
+        s.parse().unwrap()
+    }
+
+    fn p(s: &str) -> TagPattern {
+        TagPattern::try_from(s.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_filter_reason() {
+        let filter = TagFilter {
+            max_version: Some(v("v28.99")),
+            exclude: vec![p("-test$")],
+            allow_rc: false,
+            ..Default::default()
+        };
+        assert_eq!(filter.filter_reason(&v("v27.1")), None);
+        assert!(filter.filter_reason(&v("v0.20.1")).is_some());
+        assert!(filter.filter_reason(&v("v29.0")).is_some());
+        assert!(filter.filter_reason(&v("v28.0rc1")).is_some());
+        assert_eq!(
+            filter.filter_reason(&v("v27.2-test")).unwrap(),
+            "matches exclude pattern \"-test$\""
+        );
+
+        let filter: TagFilter = toml::from_str(r#"include = ["^v2[78]\\."]"#).unwrap();
+        assert_eq!(filter.filter_reason(&v("v28.0rc1")), None);
+        assert!(filter.filter_reason(&v("v26.0")).is_some());
+
+        assert!(toml::from_str::<TagFilter>(r#"include = ["("]"#).is_err());
+    }
+}
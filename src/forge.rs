@@ -0,0 +1,821 @@
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use http::header::{HeaderName, AUTHORIZATION};
+use http::{Method, StatusCode};
+use log::info;
+use octocrab::{params, Octocrab};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+
+use crate::config::{Config, GH_TOKEN_NAME};
+
+pub static GITLAB_TOKEN_NAME: &str = "GITLAB_API_TOKEN";
+pub static GITEA_TOKEN_NAME: &str = "GITEA_API_TOKEN";
+
+/// The kind of forge hosting the guix.sigs repository and our fork of it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForgeKind {
+    #[default]
+    GitHub,
+    GitLab,
+    /// Gitea and Forgejo, which share the same API.
+    Gitea,
+}
+
+impl ForgeKind {
+    /// The environment variable holding the API token for this forge.
+    pub fn token_name(&self) -> &'static str {
+        match self {
+            ForgeKind::GitHub => GH_TOKEN_NAME,
+            ForgeKind::GitLab => GITLAB_TOKEN_NAME,
+            ForgeKind::Gitea => GITEA_TOKEN_NAME,
+        }
+    }
+
+    /// Guess the forge from a repository URL, for the well-known public instances.
+    pub fn from_url(url: &str) -> Option<Self> {
+        match url_host(url)? {
+            "github.com" => Some(ForgeKind::GitHub),
+            "gitlab.com" => Some(ForgeKind::GitLab),
+            "codeberg.org" | "gitea.com" => Some(ForgeKind::Gitea),
+            _ => None,
+        }
+    }
+
+    /// The API base URL of the forge instance serving `web_url`.
+    pub fn api_url(&self, web_url: &str) -> Option<String> {
+        let host = url_host(web_url)?;
+        let scheme = web_url.split_once("://").map_or("https", |(s, _)| s);
+        match self {
+            ForgeKind::GitHub => None,
+            ForgeKind::GitLab => Some(format!("{}://{}/api/v4", scheme, host)),
+            ForgeKind::Gitea => Some(format!("{}://{}/api/v1", scheme, host)),
+        }
+    }
+}
+
+impl fmt::Display for ForgeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ForgeKind::GitHub => write!(f, "GitHub"),
+            ForgeKind::GitLab => write!(f, "GitLab"),
+            ForgeKind::Gitea => write!(f, "Gitea/Forgejo"),
+        }
+    }
+}
+
+/// The clone URL of `owner/repo` on the same forge instance as `fork_url`.
+pub fn upstream_url(fork_url: &str, owner: &str, repo: &str) -> String {
+    let scheme = fork_url.split_once("://").map_or("https", |(s, _)| s);
+    let host = url_host(fork_url).unwrap_or("github.com");
+    format!("{}://{}/{}/{}.git", scheme, host, owner, repo)
+}
+
+fn url_host(url: &str) -> Option<&str> {
+    let rest = url.split_once("://")?.1;
+    let host = rest.split('/').next()?;
+    Some(host.rsplit('@').next().unwrap_or(host)).filter(|h| !h.is_empty())
+}
+
+/// The repository path of a clone URL, e.g. `group/subgroup/guix.sigs`.
+fn repo_path(url: &str) -> Option<&str> {
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.split_once('/')?.1,
+        // scp-like syntax, e.g. git@gitlab.com:alice/guix.sigs.git
+        None => url.split_once(':')?.1,
+    };
+    let path = path.trim_matches('/');
+    Some(path.strip_suffix(".git").unwrap_or(path)).filter(|p| !p.is_empty())
+}
+
+/// An open pull request, or merge request on GitLab.
+#[derive(Debug, Clone)]
+pub struct PullRequest {
+    /// The pull request number, or the merge request IID on GitLab.
+    pub number: u64,
+    pub title: String,
+    pub url: String,
+    pub draft: bool,
+    /// The GraphQL node ID on GitHub, unused elsewhere.
+    pub node_id: Option<String>,
+}
+
+/// A forge where pull requests against the guix.sigs repository are opened.
+///
+/// Pull requests are opened from the `branch` of `head_owner`'s fork against `main` of the
+/// configured guix.sigs repository.
+#[async_trait]
+pub trait Forge: Send + Sync {
+    async fn find_open_pull_request(
+        &self,
+        head_owner: &str,
+        branch: &str,
+    ) -> Result<Option<PullRequest>>;
+
+    async fn create_pull_request(
+        &self,
+        head_owner: &str,
+        branch: &str,
+        title: &str,
+        body: &str,
+        draft: bool,
+    ) -> Result<PullRequest>;
+
+    async fn comment(&self, pull_request: &PullRequest, body: &str) -> Result<()>;
+
+    async fn add_labels(&self, pull_request: &PullRequest, labels: &[String]) -> Result<()>;
+
+    async fn request_reviewers(
+        &self,
+        pull_request: &PullRequest,
+        reviewers: &[String],
+    ) -> Result<()>;
+
+    /// Mark a draft pull request as ready for review and replace its body.
+    async fn mark_ready(&self, pull_request: &PullRequest, body: &str) -> Result<()>;
+}
+
+/// Create the forge client for the configured guix.sigs repository.
+///
+/// # Returns
+///
+/// A Result containing the Forge, or None if the forge's token is not set in the environment.
+pub fn forge_from_config(config: &Config) -> Result<Option<Box<dyn Forge>>> {
+    let token_name = config.forge.token_name();
+    let Some(token) = std::env::var(token_name).ok().filter(|t| !t.is_empty()) else {
+        return Ok(None);
+    };
+    let owner = config.guix_sigs_repo_owner.clone();
+    let repo = config.guix_sigs_repo_name.clone();
+    let api_url = || -> Result<String> {
+        config
+            .forge_url
+            .clone()
+            .or_else(|| config.forge.api_url(&config.guix_sigs_fork_url))
+            .with_context(|| format!("No API URL configured for {} (forge_url)", config.forge))
+    };
+
+    let forge: Box<dyn Forge> = match config.forge {
+        ForgeKind::GitHub => {
+            let mut builder = Octocrab::builder().personal_token(token);
+            if let Some(url) = &config.forge_url {
+                builder = builder.base_uri(url.as_str())?;
+            }
+            Box::new(GitHubForge {
+                client: builder
+                    .build()
+                    .with_context(|| format!("Couldn't build Octocrab from {}", token_name))?,
+                owner,
+                repo,
+            })
+        }
+        ForgeKind::GitLab => Box::new(GitLabForge {
+            client: rest_client(&api_url()?, HeaderName::from_static("private-token"), token)?,
+            owner,
+            repo,
+            fork_project: repo_path(&config.guix_sigs_fork_url).map(str::to_string),
+        }),
+        ForgeKind::Gitea => Box::new(GiteaForge {
+            client: rest_client(&api_url()?, AUTHORIZATION, format!("token {}", token))?,
+            owner,
+            repo,
+        }),
+    };
+    Ok(Some(forge))
+}
+
+/// Octocrab only speaks HTTP and JSON to the base URI here, so it doubles as a REST client for
+/// the other forges.
+fn rest_client(api_url: &str, header: HeaderName, value: String) -> Result<RestClient> {
+    let client = Octocrab::builder()
+        .base_uri(api_url)
+        .with_context(|| format!("Invalid forge API URL: {}", api_url))?
+        .add_header(header, value)
+        .build()
+        .context("Couldn't build forge API client")?;
+    Ok(RestClient(client))
+}
+
+/// A JSON API client for the forges other than GitHub.
+///
+/// Octocrab parses error responses as GitHub errors, which turns errors whose message is not a
+/// string, as GitLab often sends, into opaque parse errors. So responses are checked here.
+struct RestClient(Octocrab);
+
+impl RestClient {
+    /// Send a request to `route` with `query` parameters and an optional JSON body.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the response status and body.
+    async fn send(
+        &self,
+        method: Method,
+        route: &str,
+        query: &[(&str, &str)],
+        body: Option<&Value>,
+    ) -> Result<(StatusCode, String)> {
+        let mut uri = route.to_string();
+        if !query.is_empty() {
+            uri = format!("{}?{}", uri, serde_urlencoded::to_string(query)?);
+        }
+        let request = self
+            .0
+            .build_request(http::Request::builder().method(method).uri(uri), body)?;
+        let response = self.0.execute(request).await?;
+        let status = response.status();
+        let body = self.0.body_to_string(response).await?;
+        Ok((status, body))
+    }
+
+    /// Send a request and parse the JSON response.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the parsed response, or an error with the forge's message if the
+    /// request failed.
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        route: &str,
+        query: &[(&str, &str)],
+        body: Option<&Value>,
+    ) -> Result<T> {
+        let (status, body) = self.send(method, route, query, body).await?;
+        if !status.is_success() {
+            bail!("{} from {}: {}", status, route, error_message(&body));
+        }
+        serde_json::from_str(&body)
+            .with_context(|| format!("Failed to parse response from {}", route))
+    }
+
+    async fn get<T: DeserializeOwned>(&self, route: &str, query: &[(&str, &str)]) -> Result<T> {
+        self.request(Method::GET, route, query, None).await
+    }
+
+    async fn post<T: DeserializeOwned>(&self, route: &str, body: &Value) -> Result<T> {
+        self.request(Method::POST, route, &[], Some(body)).await
+    }
+
+    async fn put<T: DeserializeOwned>(&self, route: &str, body: &Value) -> Result<T> {
+        self.request(Method::PUT, route, &[], Some(body)).await
+    }
+
+    async fn patch<T: DeserializeOwned>(&self, route: &str, body: &Value) -> Result<T> {
+        self.request(Method::PATCH, route, &[], Some(body)).await
+    }
+}
+
+/// The message of a forge's error response, which GitLab sends as a string, a list or an object
+/// of messages per field.
+fn error_message(body: &str) -> String {
+    let Ok(error) = serde_json::from_str::<Value>(body) else {
+        return body.trim().to_string();
+    };
+    match error.get("message").or_else(|| error.get("error")) {
+        Some(Value::String(message)) => message.clone(),
+        Some(message) => message.to_string(),
+        None => error.to_string(),
+    }
+}
+
+struct GitHubForge {
+    client: Octocrab,
+    owner: String,
+    repo: String,
+}
+
+impl GitHubForge {
+    fn to_pull_request(pull_request: octocrab::models::pulls::PullRequest) -> PullRequest {
+        PullRequest {
+            number: pull_request.number,
+            title: pull_request.title.unwrap_or_default(),
+            url: pull_request
+                .html_url
+                .map(|u| u.to_string())
+                .unwrap_or_default(),
+            draft: pull_request.draft.unwrap_or(false),
+            node_id: pull_request.node_id,
+        }
+    }
+}
+
+#[async_trait]
+impl Forge for GitHubForge {
+    async fn find_open_pull_request(
+        &self,
+        head_owner: &str,
+        branch: &str,
+    ) -> Result<Option<PullRequest>> {
+        let page = self
+            .client
+            .pulls(&self.owner, &self.repo)
+            .list()
+            .state(params::State::Open)
+            .head(format!("{}:{}", head_owner, branch))
+            .send()
+            .await
+            .context("Failed to list open pull requests")?;
+        Ok(page.items.into_iter().next().map(Self::to_pull_request))
+    }
+
+    async fn create_pull_request(
+        &self,
+        head_owner: &str,
+        branch: &str,
+        title: &str,
+        body: &str,
+        draft: bool,
+    ) -> Result<PullRequest> {
+        let pull_request = self
+            .client
+            .pulls(&self.owner, &self.repo)
+            .create(title, format!("{}:{}", head_owner, branch), "main")
+            .body(body)
+            .draft(draft)
+            .send()
+            .await
+            .context("Failed to create pull request")?;
+        Ok(Self::to_pull_request(pull_request))
+    }
+
+    async fn comment(&self, pull_request: &PullRequest, body: &str) -> Result<()> {
+        self.client
+            .issues(&self.owner, &self.repo)
+            .create_comment(pull_request.number, body)
+            .await
+            .context("Failed to comment on pull request")?;
+        Ok(())
+    }
+
+    async fn add_labels(&self, pull_request: &PullRequest, labels: &[String]) -> Result<()> {
+        self.client
+            .issues(&self.owner, &self.repo)
+            .add_labels(pull_request.number, labels)
+            .await
+            .context("Failed to add labels to pull request")?;
+        Ok(())
+    }
+
+    async fn request_reviewers(
+        &self,
+        pull_request: &PullRequest,
+        reviewers: &[String],
+    ) -> Result<()> {
+        let _: Value = self
+            .client
+            .post(
+                format!(
+                    "/repos/{}/{}/pulls/{}/requested_reviewers",
+                    self.owner, self.repo, pull_request.number
+                ),
+                Some(&json!({ "reviewers": reviewers })),
+            )
+            .await
+            .context("Failed to request reviewers for pull request")?;
+        Ok(())
+    }
+
+    async fn mark_ready(&self, pull_request: &PullRequest, body: &str) -> Result<()> {
+        self.client
+            .pulls(&self.owner, &self.repo)
+            .update(pull_request.number)
+            .body(body)
+            .send()
+            .await
+            .context("Failed to update pull request body")?;
+
+        // The REST API cannot change the draft state, so this uses the GraphQL API
+        let node_id = pull_request
+            .node_id
+            .as_deref()
+            .context("Pull request has no node id")?;
+        let response: Value = self
+            .client
+            .graphql(&json!({
+                "query": "mutation($id: ID!) { markPullRequestReadyForReview(input: {pullRequestId: $id}) { pullRequest { isDraft } } }",
+                "variables": { "id": node_id },
+            }))
+            .await
+            .context("Failed to mark pull request ready for review")?;
+        if let Some(errors) = response.get("errors") {
+            bail!("Failed to mark pull request ready for review: {}", errors);
+        }
+        Ok(())
+    }
+}
+
+/// GitLab merge requests, opened from the fork project against the upstream project.
+struct GitLabForge {
+    client: RestClient,
+    owner: String,
+    repo: String,
+    /// The path of the fork project, from guix_sigs_fork_url.
+    fork_project: Option<String>,
+}
+
+const GITLAB_DRAFT_PREFIX: &str = "Draft: ";
+
+impl GitLabForge {
+    /// The URL-encoded path of a project, as used in API routes.
+    fn project(path: &str) -> String {
+        path.replace('/', "%2F")
+    }
+
+    fn upstream_project(&self) -> String {
+        Self::project(&format!("{}/{}", self.owner, self.repo))
+    }
+
+    fn merge_request_route(&self, pull_request: &PullRequest) -> String {
+        format!(
+            "/projects/{}/merge_requests/{}",
+            self.upstream_project(),
+            pull_request.number
+        )
+    }
+
+    fn to_pull_request(mr: &Value) -> Result<PullRequest> {
+        Ok(PullRequest {
+            number: mr["iid"].as_u64().context("Merge request has no iid")?,
+            title: mr["title"].as_str().unwrap_or_default().to_string(),
+            url: mr["web_url"].as_str().unwrap_or_default().to_string(),
+            draft: mr["draft"].as_bool().unwrap_or(false),
+            node_id: None,
+        })
+    }
+}
+
+#[async_trait]
+impl Forge for GitLabForge {
+    async fn find_open_pull_request(
+        &self,
+        head_owner: &str,
+        branch: &str,
+    ) -> Result<Option<PullRequest>> {
+        let merge_requests: Vec<Value> = self
+            .client
+            .get(
+                &format!("/projects/{}/merge_requests", self.upstream_project()),
+                &[("state", "opened"), ("source_branch", branch)],
+            )
+            .await
+            .context("Failed to list open merge requests")?;
+        merge_requests
+            .iter()
+            .find(|mr| mr["author"]["username"].as_str() == Some(head_owner))
+            .map(Self::to_pull_request)
+            .transpose()
+    }
+
+    async fn create_pull_request(
+        &self,
+        head_owner: &str,
+        branch: &str,
+        title: &str,
+        body: &str,
+        draft: bool,
+    ) -> Result<PullRequest> {
+        let upstream: Value = self
+            .client
+            .get(&format!("/projects/{}", self.upstream_project()), &[])
+            .await
+            .context("Failed to look up upstream project")?;
+        // The fork may be in a group or named differently, so its URL is the reliable source
+        let fork_project = match &self.fork_project {
+            Some(path) => Self::project(path),
+            None => Self::project(&format!("{}/{}", head_owner, self.repo)),
+        };
+        let title = if draft {
+            format!("{}{}", GITLAB_DRAFT_PREFIX, title)
+        } else {
+            title.to_string()
+        };
+        let mr: Value = self
+            .client
+            .post(
+                &format!("/projects/{}/merge_requests", fork_project),
+                &json!({
+                    "source_branch": branch,
+                    "target_branch": "main",
+                    "target_project_id": upstream["id"],
+                    "title": title,
+                    "description": body,
+                }),
+            )
+            .await
+            .context("Failed to create merge request")?;
+        Self::to_pull_request(&mr)
+    }
+
+    async fn comment(&self, pull_request: &PullRequest, body: &str) -> Result<()> {
+        let _: Value = self
+            .client
+            .post(
+                &format!("{}/notes", self.merge_request_route(pull_request)),
+                &json!({ "body": body }),
+            )
+            .await
+            .context("Failed to comment on merge request")?;
+        Ok(())
+    }
+
+    async fn add_labels(&self, pull_request: &PullRequest, labels: &[String]) -> Result<()> {
+        let _: Value = self
+            .client
+            .put(
+                &self.merge_request_route(pull_request),
+                &json!({ "add_labels": labels.join(",") }),
+            )
+            .await
+            .context("Failed to add labels to merge request")?;
+        Ok(())
+    }
+
+    async fn request_reviewers(
+        &self,
+        pull_request: &PullRequest,
+        reviewers: &[String],
+    ) -> Result<()> {
+        let mut reviewer_ids = Vec::new();
+        for username in reviewers {
+            let users: Vec<Value> = self
+                .client
+                .get("/users", &[("username", username)])
+                .await
+                .with_context(|| format!("Failed to look up GitLab user {}", username))?;
+            match users.first().and_then(|u| u["id"].as_u64()) {
+                Some(id) => reviewer_ids.push(id),
+                None => bail!("GitLab user {} not found", username),
+            }
+        }
+        let _: Value = self
+            .client
+            .put(
+                &self.merge_request_route(pull_request),
+                &json!({ "reviewer_ids": reviewer_ids }),
+            )
+            .await
+            .context("Failed to request reviewers for merge request")?;
+        Ok(())
+    }
+
+    async fn mark_ready(&self, pull_request: &PullRequest, body: &str) -> Result<()> {
+        let title = pull_request
+            .title
+            .trim_start_matches(GITLAB_DRAFT_PREFIX)
+            .to_string();
+        let _: Value = self
+            .client
+            .put(
+                &self.merge_request_route(pull_request),
+                &json!({ "title": title, "description": body }),
+            )
+            .await
+            .context("Failed to mark merge request ready")?;
+        Ok(())
+    }
+}
+
+/// Gitea and Forgejo pull requests.
+struct GiteaForge {
+    client: RestClient,
+    owner: String,
+    repo: String,
+}
+
+const GITEA_DRAFT_PREFIX: &str = "WIP: ";
+
+impl GiteaForge {
+    fn repo_route(&self) -> String {
+        format!("/repos/{}/{}", self.owner, self.repo)
+    }
+
+    fn to_pull_request(pr: &Value) -> Result<PullRequest> {
+        let title = pr["title"].as_str().unwrap_or_default().to_string();
+        Ok(PullRequest {
+            number: pr["number"]
+                .as_u64()
+                .context("Pull request has no number")?,
+            draft: title.starts_with(GITEA_DRAFT_PREFIX),
+            title,
+            url: pr["html_url"].as_str().unwrap_or_default().to_string(),
+            node_id: None,
+        })
+    }
+}
+
+#[async_trait]
+impl Forge for GiteaForge {
+    async fn find_open_pull_request(
+        &self,
+        head_owner: &str,
+        branch: &str,
+    ) -> Result<Option<PullRequest>> {
+        let route = format!("{}/pulls/main/{}:{}", self.repo_route(), head_owner, branch);
+        let (status, body) = self
+            .client
+            .send(Method::GET, &route, &[], None)
+            .await
+            .context("Failed to look up pull request")?;
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            bail!(
+                "Failed to look up pull request: {} from {}: {}",
+                status,
+                route,
+                error_message(&body)
+            );
+        }
+        let pr: Value = serde_json::from_str(&body)
+            .with_context(|| format!("Failed to parse response from {}", route))?;
+        // The latest pull request for the branch is returned even once it has been closed
+        if pr["state"].as_str() != Some("open") {
+            return Ok(None);
+        }
+        Self::to_pull_request(&pr).map(Some)
+    }
+
+    async fn create_pull_request(
+        &self,
+        head_owner: &str,
+        branch: &str,
+        title: &str,
+        body: &str,
+        draft: bool,
+    ) -> Result<PullRequest> {
+        let title = if draft {
+            format!("{}{}", GITEA_DRAFT_PREFIX, title)
+        } else {
+            title.to_string()
+        };
+        let pr: Value = self
+            .client
+            .post(
+                &format!("{}/pulls", self.repo_route()),
+                &json!({
+                    "head": format!("{}:{}", head_owner, branch),
+                    "base": "main",
+                    "title": title,
+                    "body": body,
+                }),
+            )
+            .await
+            .context("Failed to create pull request")?;
+        Self::to_pull_request(&pr)
+    }
+
+    async fn comment(&self, pull_request: &PullRequest, body: &str) -> Result<()> {
+        let _: Value = self
+            .client
+            .post(
+                &format!(
+                    "{}/issues/{}/comments",
+                    self.repo_route(),
+                    pull_request.number
+                ),
+                &json!({ "body": body }),
+            )
+            .await
+            .context("Failed to comment on pull request")?;
+        Ok(())
+    }
+
+    async fn add_labels(&self, pull_request: &PullRequest, labels: &[String]) -> Result<()> {
+        // Gitea only accepts label IDs
+        let repo_labels: Vec<Value> = self
+            .client
+            .get(&format!("{}/labels", self.repo_route()), &[])
+            .await
+            .context("Failed to list repository labels")?;
+        let mut ids = Vec::new();
+        for label in labels {
+            match repo_labels
+                .iter()
+                .find(|l| l["name"].as_str() == Some(label.as_str()))
+                .and_then(|l| l["id"].as_u64())
+            {
+                Some(id) => ids.push(id),
+                None => info!(
+                    "Label {:?} does not exist in {}, skipping",
+                    label, self.repo
+                ),
+            }
+        }
+        let _: Value = self
+            .client
+            .post(
+                &format!(
+                    "{}/issues/{}/labels",
+                    self.repo_route(),
+                    pull_request.number
+                ),
+                &json!({ "labels": ids }),
+            )
+            .await
+            .context("Failed to add labels to pull request")?;
+        Ok(())
+    }
+
+    async fn request_reviewers(
+        &self,
+        pull_request: &PullRequest,
+        reviewers: &[String],
+    ) -> Result<()> {
+        let _: Value = self
+            .client
+            .post(
+                &format!(
+                    "{}/pulls/{}/requested_reviewers",
+                    self.repo_route(),
+                    pull_request.number
+                ),
+                &json!({ "reviewers": reviewers }),
+            )
+            .await
+            .context("Failed to request reviewers for pull request")?;
+        Ok(())
+    }
+
+    async fn mark_ready(&self, pull_request: &PullRequest, body: &str) -> Result<()> {
+        let title = pull_request
+            .title
+            .trim_start_matches(GITEA_DRAFT_PREFIX)
+            .to_string();
+        let _: Value = self
+            .client
+            .patch(
+                &format!("{}/pulls/{}", self.repo_route(), pull_request.number),
+                &json!({ "title": title, "body": body }),
+            )
+            .await
+            .context("Failed to mark pull request ready")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forge_kind_from_url() {
+        assert_eq!(
+            ForgeKind::from_url("https://github.com/alice/guix.sigs"),
+            Some(ForgeKind::GitHub)
+        );
+        assert_eq!(
+            ForgeKind::from_url("https://gitlab.com/alice/guix.sigs.git"),
+            Some(ForgeKind::GitLab)
+        );
+        assert_eq!(
+            ForgeKind::from_url("https://git.example.org/alice/guix.sigs"),
+            None
+        );
+        assert_eq!(ForgeKind::from_url("git@github.com:alice/guix.sigs"), None);
+        assert_eq!(
+            ForgeKind::Gitea
+                .api_url("http://localhost:3000/alice/guix.sigs")
+                .as_deref(),
+            Some("http://localhost:3000/api/v1")
+        );
+        assert_eq!(
+            ForgeKind::GitHub.api_url("https://github.com/alice/guix.sigs"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_repo_path() {
+        assert_eq!(
+            repo_path("https://gitlab.com/group/sub/guix.sigs.git"),
+            Some("group/sub/guix.sigs")
+        );
+        assert_eq!(
+            repo_path("git@gitlab.com:alice/guix.sigs.git"),
+            Some("alice/guix.sigs")
+        );
+        assert_eq!(repo_path("https://gitlab.com/"), None);
+    }
+
+    #[test]
+    fn test_error_message() {
+        assert_eq!(
+            error_message(r#"{"message": "404 Project Not Found"}"#),
+            "404 Project Not Found"
+        );
+        assert_eq!(
+            error_message(r#"{"message": ["Another open merge request already exists"]}"#),
+            r#"["Another open merge request already exists"]"#
+        );
+        assert_eq!(
+            error_message(r#"{"message": {"title": ["can't be blank"]}}"#),
+            r#"{"title":["can't be blank"]}"#
+        );
+        assert_eq!(error_message("Bad Gateway\n"), "Bad Gateway");
+    }
+}
@@ -0,0 +1,320 @@
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+/// Rotation of the watcher daemon's log file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// Rotate the log once it grows past this many megabytes.
+    pub max_size_mb: Option<u64>,
+    /// Rotate the log once it is this many hours old.
+    pub max_age_hours: Option<u64>,
+    /// Number of rotated logs to keep.
+    pub keep: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            max_size_mb: Some(50),
+            max_age_hours: None,
+            keep: 5,
+        }
+    }
+}
+
+impl fmt::Display for LogConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rotate at {}, after {}, keep {}",
+            self.max_size_mb
+                .map_or("any size".to_string(), |mb| format!("{} MB", mb)),
+            self.max_age_hours
+                .map_or("any age".to_string(), |h| format!("{}h", h)),
+            self.keep
+        )
+    }
+}
+
+/// Number of output lines of a failed build step included in its error.
+pub const ERROR_TAIL_LINES: usize = 20;
+
+/// Output of a build step, saved with timestamps to `<dir>/<step>.log` and kept in memory as
+/// its last lines.
+pub struct StepLog {
+    path: PathBuf,
+    file: Option<File>,
+    tail: VecDeque<String>,
+}
+
+impl StepLog {
+    /// Open the log of `step` in `dir`, appending if the step has run before.
+    ///
+    /// A log file that can't be opened is warned about rather than failing the step.
+    pub fn open(dir: &Path, step: &str) -> Self {
+        let path = dir.join(format!("{}.log", step));
+        let file = fs::create_dir_all(dir)
+            .and_then(|_| OpenOptions::new().create(true).append(true).open(&path));
+        let file = match file {
+            Ok(file) => Some(file),
+            Err(e) => {
+                warn!("Failed to open build step log {:?}: {}", path, e);
+                None
+            }
+        };
+        Self {
+            path,
+            file,
+            tail: VecDeque::with_capacity(ERROR_TAIL_LINES),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&mut self, line: &str) {
+        if let Some(file) = self.file.as_mut() {
+            let timestamp = humantime::format_rfc3339_seconds(SystemTime::now());
+            if let Err(e) = writeln!(file, "[{}] {}", timestamp, line) {
+                warn!("Failed to write build step log {:?}: {}", self.path, e);
+                self.file = None;
+            }
+        }
+        if self.tail.len() == ERROR_TAIL_LINES {
+            self.tail.pop_front();
+        }
+        self.tail.push_back(line.to_string());
+    }
+
+    /// The last lines of output, oldest first.
+    pub fn tail(&self) -> String {
+        self.tail
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// How long to wait at exit for output still in the log pipe, which build steps that outlived
+/// the watcher may hold open.
+const LOG_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The pipe stdout and stderr are redirected to, and the thread writing it to the log file.
+pub struct LogPipe {
+    path: PathBuf,
+    drained: mpsc::Receiver<()>,
+    thread: JoinHandle<()>,
+}
+
+impl LogPipe {
+    /// Point stdout and stderr straight at the log file, closing the pipe, and wait until the
+    /// output still in the pipe has been written.
+    pub fn finish(self) {
+        let _ = std::io::stdout().flush();
+        if let Ok(file) = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+        {
+            unsafe {
+                libc::dup2(file.as_raw_fd(), libc::STDOUT_FILENO);
+                libc::dup2(file.as_raw_fd(), libc::STDERR_FILENO);
+            }
+        }
+        if self.drained.recv_timeout(LOG_DRAIN_TIMEOUT).is_ok() {
+            let _ = self.thread.join();
+        }
+    }
+}
+
+/// Send everything written to stdout and stderr, including by child processes, through a pipe
+/// to `path`, rotating it as configured.
+///
+/// Child processes keep writing to the pipe across a rotation, so a long build never holds on to
+/// a rotated file.
+pub fn redirect_output(path: &Path, config: LogConfig) -> Result<LogPipe> {
+    let mut log = RotatingLog::open(path, config)?;
+    let mut fds = [0; 2];
+    unsafe {
+        if libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) == -1 {
+            return Err(std::io::Error::last_os_error()).context("Failed to create log pipe");
+        }
+    }
+    let reader = unsafe { File::from_raw_fd(fds[0]) };
+    unsafe {
+        // dup2 clears O_CLOEXEC, so child processes inherit the write end as stdout and stderr
+        if libc::dup2(fds[1], libc::STDOUT_FILENO) == -1
+            || libc::dup2(fds[1], libc::STDERR_FILENO) == -1
+        {
+            return Err(std::io::Error::last_os_error())
+                .context("Failed to redirect output to log pipe");
+        }
+        libc::close(fds[1]);
+    }
+
+    let (drained_tx, drained) = mpsc::channel();
+    let thread = std::thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut line = Vec::new();
+        // Errors can't be logged from here as stderr feeds back into this pipe
+        while reader.read_until(b'\n', &mut line).unwrap_or(0) > 0 {
+            let _ = log.write(&line);
+            line.clear();
+        }
+        let _ = drained_tx.send(());
+    });
+    Ok(LogPipe {
+        path: path.to_path_buf(),
+        drained,
+        thread,
+    })
+}
+
+/// A log file that is rotated to `<path>.1`, `<path>.2`, ... once it is too large or too old.
+struct RotatingLog {
+    path: PathBuf,
+    config: LogConfig,
+    file: File,
+    size: u64,
+    created: SystemTime,
+}
+
+impl RotatingLog {
+    fn open(path: &Path, config: LogConfig) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open log file: {:?}", path))?;
+        let metadata = file
+            .metadata()
+            .with_context(|| format!("Failed to read log file metadata: {:?}", path))?;
+        let mut log = Self {
+            path: path.to_path_buf(),
+            config,
+            file,
+            size: metadata.len(),
+            created: metadata.created().unwrap_or_else(|_| SystemTime::now()),
+        };
+        if log.rotation_due() {
+            log.rotate()?;
+        }
+        Ok(log)
+    }
+
+    fn rotation_due(&self) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        let too_large = self
+            .config
+            .max_size_mb
+            .is_some_and(|mb| self.size >= mb * 1024 * 1024);
+        let too_old = self.config.max_age_hours.is_some_and(|hours| {
+            self.created.elapsed().unwrap_or_default() >= Duration::from_secs(hours * 3600)
+        });
+        too_large || too_old
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        if self.rotation_due() {
+            self.rotate()?;
+        }
+        self.file
+            .write_all(data)
+            .with_context(|| format!("Failed to write log file: {:?}", self.path))?;
+        self.size += data.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        rotate_files(&self.path, self.config.keep)?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open log file: {:?}", self.path))?;
+        self.size = 0;
+        self.created = SystemTime::now();
+        Ok(())
+    }
+}
+
+/// Shift `<path>.1` .. `<path>.<keep - 1>` up by one and move `path` to `<path>.1`, deleting
+/// the oldest log.
+fn rotate_files(path: &Path, keep: usize) -> Result<()> {
+    let rotated = |n: usize| PathBuf::from(format!("{}.{}", path.display(), n));
+    if keep == 0 {
+        return fs::remove_file(path)
+            .with_context(|| format!("Failed to remove log file: {:?}", path));
+    }
+    for n in (1..keep).rev() {
+        if rotated(n).exists() {
+            fs::rename(rotated(n), rotated(n + 1))
+                .with_context(|| format!("Failed to rotate log file: {:?}", rotated(n)))?;
+        }
+    }
+    fs::rename(path, rotated(1)).with_context(|| format!("Failed to rotate log file: {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotating_log() {
+        let dir = std::env::temp_dir().join(format!("bgt-logs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("watch.log");
+        fs::write(&path, "old\n").unwrap();
+        let config = LogConfig {
+            max_size_mb: Some(1),
+            keep: 2,
+            ..Default::default()
+        };
+
+        let mut log = RotatingLog::open(&path, config).unwrap();
+        log.write(&vec![b'a'; 1024 * 1024]).unwrap();
+        log.write(b"second\n").unwrap();
+        log.rotate().unwrap();
+        log.write(b"third\n").unwrap();
+
+        let read = |name: &str| fs::read(dir.join(name)).unwrap();
+        assert_eq!(read("watch.log"), b"third\n");
+        assert_eq!(read("watch.log.1"), b"second\n");
+        assert_eq!(read("watch.log.2").len(), 4 + 1024 * 1024);
+        assert!(!dir.join("watch.log.3").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_step_log() {
+        let dir = std::env::temp_dir().join(format!("bgt-step-log-{}", std::process::id()));
+        let mut log = StepLog::open(&dir.join("v28.0"), "guix-build");
+        for i in 0..ERROR_TAIL_LINES + 5 {
+            log.record(&format!("line {}", i));
+        }
+
+        let contents = fs::read_to_string(log.path()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(contents.lines().count(), ERROR_TAIL_LINES + 5);
+        assert!(contents.lines().all(|l| l.starts_with('[')));
+        assert!(log.tail().starts_with("line 5\n"));
+        assert!(log
+            .tail()
+            .ends_with(&format!("line {}", ERROR_TAIL_LINES + 4)));
+    }
+}
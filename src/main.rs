@@ -12,11 +12,23 @@ use clap::Parser;
 use env_logger::Env;
 use log::info;
 
+mod attestations;
+mod build_info;
 mod builder;
 mod commands;
 mod config;
+mod control;
 mod daemon;
 mod fetcher;
+mod filter;
+mod forge;
+mod logging;
+mod notify;
+mod process;
+mod signing;
+mod state;
+mod status;
+mod verify;
 mod version;
 mod watcher;
 mod wizard;
@@ -25,11 +37,21 @@ mod xor;
 use builder::{BuildAction, BuildArgs};
 use clap::Subcommand;
 use config::Config;
+use std::path::Path;
+use std::time::Duration;
+use version::BitcoinVersion;
 
+use crate::builder::clone_guix_sigs;
 use crate::commands::{create_builder, run_watcher};
-use crate::config::{get_config_file, read_config, GH_TOKEN_NAME};
+use crate::config::{get_config_file, read_config, ConfigOverrides};
+use crate::control::{send_command, ControlCommand};
 use crate::daemon::{start_daemon, stop_daemon};
-use crate::fetcher::fetch_all_tags;
+use crate::fetcher::{fetch_all_tags, TagSources};
+use crate::logging::LogPipe;
+use crate::process::is_alive;
+use crate::signing::SignerConfig;
+use crate::status::WatchStatus;
+use crate::verify::verify_signatures;
 use crate::wizard::init_wizard;
 
 #[derive(Parser)]
@@ -42,6 +64,10 @@ struct Cli {
     #[arg(long)]
     multi_package: bool,
 
+    /// Only build these host triplets (sets `HOSTS`), e.g. `--hosts x86_64-linux-gnu`
+    #[arg(long, global = true, value_delimiter = ',')]
+    hosts: Vec<String>,
+
     /// Enable debug log level
     #[arg(long, global = true)]
     debug: bool,
@@ -54,12 +80,12 @@ enum Commands {
     /// Build a specific tag
     Build {
         /// The tag to build
-        tag: String,
+        tag: BitcoinVersion,
     },
     /// Attest to non-codesigned build outputs
     Attest {
         /// The tag to attest to
-        tag: String,
+        tag: BitcoinVersion,
         /// Attempt to automatically sign using gpg and automatically open a PR on GitHub
         #[arg(long)]
         auto: bool,
@@ -67,7 +93,7 @@ enum Commands {
     /// Attach codesignatures to existing non-codesigned outputs and attest
     Codesign {
         /// The tag to codesign
-        tag: String,
+        tag: BitcoinVersion,
         /// Attempt to automatically sign using gpg and automatically open a PR on GitHub
         #[arg(long)]
         auto: bool,
@@ -83,6 +109,14 @@ enum Commands {
     ShowConfig,
     /// Guix build current master to populate Guix caches
     Warmup,
+    /// Verify the GPG signatures of every signer's attestations for a tag
+    Verify {
+        /// The tag to verify
+        tag: BitcoinVersion,
+        /// Fail unless at least this many signers have only valid signatures
+        #[arg(long)]
+        min_valid: Option<usize>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -99,12 +133,32 @@ enum WatchAction {
         #[arg(long)]
         dry_run: bool,
     },
+    /// Show whether the watcher is running and what it is doing
+    Status,
+    /// Make the running watcher poll for new tags now
+    Poll,
+    /// Make the running watcher build, attest and codesign a tag
+    Enqueue {
+        /// The tag to process
+        tag: BitcoinVersion,
+    },
+    /// Stop the running watcher from starting new jobs
+    Pause,
+    /// Let a paused watcher start new jobs again
+    Resume,
+    /// Cancel the job the running watcher is working on
+    Cancel,
+    /// Make the running watcher reload the config file
+    Reload,
     /// Stop the watcher daemon
-    Stop,
+    Stop {
+        /// Seconds to wait for the watcher to shut down before killing it (default: stop_timeout)
+        #[arg(long)]
+        timeout: Option<u64>,
+    },
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     let cli = Cli::parse();
     let log_level = if cli.debug { "debug" } else { "info" };
     env_logger::Builder::from_env(Env::default().default_filter_or(log_level)).init();
@@ -114,36 +168,66 @@ async fn main() -> Result<()> {
         Commands::Setup => Config::default(),
         _ => read_config().context("Failed to read config")?,
     };
-    if cli.multi_package {
-        config.multi_package = true;
-    }
+    let overrides = ConfigOverrides {
+        multi_package: cli.multi_package,
+        hosts: cli.hosts,
+    };
+    overrides.apply(&mut config);
 
     // Check for GH_API_TOKEN early when needed
+    #[allow(clippy::collapsible_match)]
     match &cli.command {
         Commands::Attest { auto, .. }
         | Commands::Codesign { auto, .. }
         | Commands::Watch {
             action: WatchAction::Start { auto, .. },
         } => {
-            if *auto && std::env::var(GH_TOKEN_NAME).is_err() {
+            if *auto && std::env::var(config.forge.token_name()).is_err() {
                 bail!(
                     "{} environment variable is not set. Please set it and try again.",
-                    GH_TOKEN_NAME
+                    config.forge.token_name()
                 );
             }
         }
         _ => {}
     }
 
-    match cli.command {
+    // Daemonize before starting the async runtime, as its worker threads don't survive a fork
+    let log_pipe = match &cli.command {
+        Commands::Watch {
+            action: WatchAction::Start { auto, daemon, .. },
+        } => prepare_watcher(&config, *auto, *daemon)?,
+        _ => None,
+    };
+    // Build steps block a worker while they wait on their child process, so keep at least one
+    // more for the control socket and signal handling
+    let workers = std::thread::available_parallelism().map_or(2, |n| n.get().max(2));
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(workers)
+        .enable_all()
+        .build()
+        .context("Failed to start async runtime")?;
+    let result = runtime.block_on(run(cli.command, &config, &overrides));
+    drop(runtime);
+    if let Some(log_pipe) = log_pipe {
+        // Write out the watcher's last output, so the error returned below ends up in the log
+        log_pipe.finish();
+    }
+    result
+}
+
+/// Run a command
+async fn run(command: Commands, config: &Config, overrides: &ConfigOverrides) -> Result<()> {
+    match command {
         Commands::Setup => setup().await?,
-        Commands::Build { tag } => build(&config, &tag).await?,
-        Commands::Attest { tag, auto } => attest(&config, &tag, auto).await?,
-        Commands::Codesign { tag, auto } => codesign(&config, &tag, auto).await?,
-        Commands::Watch { action } => watch(&config, action).await?,
-        Commands::Clean => clean(&config).await?,
-        Commands::ShowConfig => show_config(&config),
-        Commands::Warmup => warmup(&config).await?,
+        Commands::Build { tag } => build(config, &tag).await?,
+        Commands::Attest { tag, auto } => attest(config, &tag, auto).await?,
+        Commands::Codesign { tag, auto } => codesign(config, &tag, auto).await?,
+        Commands::Watch { action } => watch(config, overrides, action).await?,
+        Commands::Clean => clean(config).await?,
+        Commands::ShowConfig => show_config(config),
+        Commands::Warmup => warmup(config).await?,
+        Commands::Verify { tag, min_valid } => verify(config, &tag, min_valid)?,
     }
 
     Ok(())
@@ -161,10 +245,10 @@ async fn setup() -> Result<()> {
 }
 
 /// Build a specific tag
-async fn build(config: &Config, tag: &str) -> Result<()> {
+async fn build(config: &Config, tag: &BitcoinVersion) -> Result<()> {
     let args = BuildArgs {
         action: BuildAction::Build,
-        tag: Some(tag.to_string()),
+        tag: Some(tag.clone()),
         ..Default::default()
     };
     let builder = create_builder(config, args)
@@ -177,10 +261,10 @@ async fn build(config: &Config, tag: &str) -> Result<()> {
 }
 
 /// Attest to non-codesigned build outputs
-async fn attest(config: &Config, tag: &str, auto: bool) -> Result<()> {
+async fn attest(config: &Config, tag: &BitcoinVersion, auto: bool) -> Result<()> {
     let args = BuildArgs {
         action: BuildAction::NonCodeSigned,
-        tag: Some(tag.to_string()),
+        tag: Some(tag.clone()),
         auto,
     };
     let builder = create_builder(config, args)
@@ -193,10 +277,10 @@ async fn attest(config: &Config, tag: &str, auto: bool) -> Result<()> {
 }
 
 /// Attach codesignatures to existing non-codesigned outputs and attest
-async fn codesign(config: &Config, tag: &str, auto: bool) -> Result<()> {
+async fn codesign(config: &Config, tag: &BitcoinVersion, auto: bool) -> Result<()> {
     let args = BuildArgs {
         action: BuildAction::CodeSigned,
-        tag: Some(tag.to_string()),
+        tag: Some(tag.clone()),
         auto,
     };
     let builder = create_builder(config, args)
@@ -209,48 +293,129 @@ async fn codesign(config: &Config, tag: &str, auto: bool) -> Result<()> {
 }
 
 /// Run a continuous watcher to monitor for new tags and automatically build them
-async fn watch(config: &Config, action: WatchAction) -> Result<()> {
+async fn watch(config: &Config, overrides: &ConfigOverrides, action: WatchAction) -> Result<()> {
     let pid_file = get_config_file("watch.pid");
-    let log_file = get_config_file("watch.log");
 
     match action {
-        WatchAction::Start {
-            auto,
-            daemon,
-            dry_run,
-        } => {
-            if auto {
-                info!("Checking for automatic GPG signing capability when using --auto flag...");
-                check_gpg_signing(&config.gpg_key_id)
-                    .context("Failed to verify GPG signing capability")?;
-                info!("GPG signing check passed.");
-            }
-            if daemon {
-                info!("Starting BGT watcher as a daemon...");
-                info!("View logs at: {}.", log_file.display());
-                start_daemon(&pid_file, &log_file).context("Failed to start daemon")?;
-            } else {
-                info!("Starting BGT watcher in the foreground...");
-            }
-            let (mut seen_tags_bitcoin, mut seen_tags_sigs) = fetch_all_tags(config)
-                .await
-                .context("Failed to fetch initial tags")?;
-            let args = BuildArgs {
-                auto,
-                ..Default::default()
-            };
-            create_builder(config, args)
-                .await
-                .context("Failed to initialize builder")?;
-            run_watcher(config, &mut seen_tags_bitcoin, &mut seen_tags_sigs, dry_run)
-                .await
-                .context("Watcher encountered an error")
+        WatchAction::Start { auto, dry_run, .. } => {
+            start_watcher(config, overrides, auto, dry_run).await
         }
-        WatchAction::Stop => {
+        WatchAction::Status => watch_status(&pid_file),
+        WatchAction::Poll => control_watcher(ControlCommand::Poll),
+        WatchAction::Enqueue { tag } => control_watcher(ControlCommand::Enqueue {
+            tag: tag.to_string(),
+        }),
+        WatchAction::Pause => control_watcher(ControlCommand::Pause),
+        WatchAction::Resume => control_watcher(ControlCommand::Resume),
+        WatchAction::Cancel => control_watcher(ControlCommand::Cancel),
+        WatchAction::Reload => control_watcher(ControlCommand::Reload),
+        WatchAction::Stop { timeout } => {
             info!("Stopping BGT watcher daemon...");
-            stop_daemon(&pid_file).context("Failed to stop daemon")
+            let timeout = timeout.map_or(config.stop_timeout, Duration::from_secs);
+            stop_daemon(&pid_file, timeout).context("Failed to stop daemon")
+        }
+    }
+}
+
+/// Check the watcher can run and daemonize it if requested
+///
+/// # Returns
+///
+/// A Result containing the pipe the daemon's output goes through, or None in the foreground.
+fn prepare_watcher(config: &Config, auto: bool, daemon: bool) -> Result<Option<LogPipe>> {
+    if auto && config.signer == SignerConfig::Gpg {
+        info!("Checking for automatic GPG signing capability when using --auto flag...");
+        check_gpg_signing(&config.gpg_key_id).context("Failed to verify GPG signing capability")?;
+        info!("GPG signing check passed.");
+    }
+    if !daemon {
+        info!("Starting BGT watcher in the foreground...");
+        return Ok(None);
+    }
+    let log_file = get_config_file("watch.log");
+    info!("Starting BGT watcher as a daemon...");
+    info!("View logs at: {}.", log_file.display());
+    let log_pipe = start_daemon(&get_config_file("watch.pid"), &log_file, &config.log)
+        .context("Failed to start daemon")?;
+    Ok(Some(log_pipe))
+}
+
+/// Fetch the known tags and run the watcher loop until it stops
+async fn start_watcher(
+    config: &Config,
+    overrides: &ConfigOverrides,
+    auto: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let sources = TagSources::from_config(config).context("Failed to create tag sources")?;
+    let (mut seen_tags_bitcoin, mut seen_tags_sigs) = fetch_all_tags(&sources)
+        .await
+        .context("Failed to fetch initial tags")?;
+    let args = BuildArgs {
+        auto,
+        ..Default::default()
+    };
+    create_builder(config, args)
+        .await
+        .context("Failed to initialize builder")?;
+    run_watcher(
+        config,
+        overrides,
+        sources,
+        &mut seen_tags_bitcoin,
+        &mut seen_tags_sigs,
+        auto,
+        dry_run,
+    )
+    .await
+    .context("Watcher encountered an error")
+}
+
+/// Send a command to the running watcher over its control socket and print the reply
+fn control_watcher(command: ControlCommand) -> Result<()> {
+    let reply = send_command(&get_config_file("watch.sock"), &command)
+        .with_context(|| format!("Watcher did not accept command {:?}", command))?;
+    println!("{}", reply);
+    Ok(())
+}
+
+/// Report whether the watcher is alive and the status it last published
+fn watch_status(pid_file: &Path) -> Result<()> {
+    let status = WatchStatus::load(get_config_file("watch_status.json"))
+        .context("Failed to load watcher status")?;
+
+    let daemon_pid = if pid_file.exists() {
+        let pid = std::fs::read_to_string(pid_file)
+            .with_context(|| format!("Failed to read PID from file: {:?}", pid_file))?
+            .trim()
+            .parse::<i32>()
+            .context("Failed to parse PID as integer")?;
+        Some(pid)
+    } else {
+        None
+    };
+    match (daemon_pid, &status) {
+        (Some(pid), _) if is_alive(pid) => println!("Watcher daemon running (pid {})", pid),
+        (Some(pid), _) => println!(
+            "Watcher daemon not running (stale PID file {:?} for pid {})",
+            pid_file, pid
+        ),
+        (None, Some(status)) if is_alive(status.pid as i32) => {
+            println!("Watcher running in the foreground (pid {})", status.pid)
         }
+        (None, _) => println!("Watcher not running"),
     }
+
+    match status {
+        Some(mut status) => {
+            if !is_alive(status.pid as i32) {
+                status.clear_activity();
+            }
+            println!("{}", status)
+        }
+        None => println!("No watcher status has been published yet"),
+    }
+    Ok(())
 }
 
 /// Clean up guix build directories leaving caches intact
@@ -285,6 +450,27 @@ async fn warmup(config: &Config) -> Result<()> {
         .context("Build process for tag warmup failed")
 }
 
+/// Verify the GPG signatures of every signer's attestations for a tag
+fn verify(config: &Config, tag: &BitcoinVersion, min_valid: Option<usize>) -> Result<()> {
+    clone_guix_sigs(config)?;
+    info!("Verifying signatures for {}", tag);
+    let report = verify_signatures(&config.guix_sigs_dir, &tag.dir_name())
+        .with_context(|| format!("Signature verification for tag {} failed", tag))?;
+    println!("{}", report);
+
+    if let Some(min_valid) = min_valid {
+        if report.valid_count() < min_valid {
+            bail!(
+                "Only {} valid attestations for {}, {} required",
+                report.valid_count(),
+                tag,
+                min_valid
+            );
+        }
+    }
+    Ok(())
+}
+
 /// Check if GPG signing is possible with the given key short ID
 fn check_gpg_signing(key_id: &str) -> Result<()> {
     use anyhow::bail;
@@ -0,0 +1,26 @@
+use log::{debug, error};
+use std::process::Command;
+
+use crate::config::Config;
+
+/// Send a notification about `event` through the configured `notify_command`, if any.
+///
+/// The command is run with `sh -c` and receives the event name and message in the
+/// `BGT_EVENT` and `BGT_MESSAGE` environment variables. Failures are logged and otherwise
+/// ignored, so a broken notifier never stops a build.
+pub fn notify(config: &Config, event: &str, message: &str) {
+    let Some(command) = config.notify_command.as_deref().filter(|c| !c.is_empty()) else {
+        return;
+    };
+    debug!("Sending {} notification", event);
+    match Command::new("sh")
+        .args(["-c", command])
+        .env("BGT_EVENT", event)
+        .env("BGT_MESSAGE", message)
+        .status()
+    {
+        Ok(status) if status.success() => {}
+        Ok(status) => error!("Notification command exited with {}", status),
+        Err(e) => error!("Failed to run notification command: {}", e),
+    }
+}
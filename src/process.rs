@@ -0,0 +1,109 @@
+use anyhow::{bail, Result};
+use log::{info, warn};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::time::Duration;
+
+use crate::config::get_config_file;
+
+/// Process group of the build step currently running, or 0 if none is.
+static RUNNING_GROUP: AtomicI32 = AtomicI32::new(0);
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+static MANAGE_GROUPS: AtomicBool = AtomicBool::new(false);
+
+/// Run build steps in their own process groups from now on.
+///
+/// Only enable this when a signal handler calls request_shutdown, as children in their own
+/// group no longer receive the terminal's Ctrl+C.
+pub fn manage_child_groups() {
+    MANAGE_GROUPS.store(true, Ordering::SeqCst);
+}
+
+pub fn child_groups_managed() -> bool {
+    MANAGE_GROUPS.load(Ordering::SeqCst)
+}
+
+/// Registration of a running child process group, cleared when dropped.
+pub struct RunningGroup;
+
+impl Drop for RunningGroup {
+    fn drop(&mut self) {
+        RUNNING_GROUP.store(0, Ordering::SeqCst);
+        if child_groups_managed() {
+            let _ = fs::remove_file(running_group_file());
+        }
+    }
+}
+
+/// File the process group of the running build step is published to, so `bgt watch stop` can
+/// kill it if the watcher does not exit.
+pub fn running_group_file() -> PathBuf {
+    get_config_file("watch.pgid")
+}
+
+/// Register a child spawned as the leader of its own process group, so a shutdown can cancel
+/// it together with everything it spawned.
+pub fn register_group(pid: u32) -> RunningGroup {
+    RUNNING_GROUP.store(pid as i32, Ordering::SeqCst);
+    if child_groups_managed() {
+        if let Err(e) = fs::write(running_group_file(), pid.to_string()) {
+            warn!("Failed to publish running process group {}: {}", pid, e);
+        }
+    }
+    RunningGroup
+}
+
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Request a shutdown and cancel the running child process group, if any.
+///
+/// The group is sent SIGTERM, and SIGKILL if it is still running after `timeout`.
+pub fn request_shutdown(timeout: Duration) {
+    SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+    if let Err(e) = cancel_running_group(timeout) {
+        warn!("{:#}", e);
+    }
+}
+
+/// Cancel the running child process group without shutting down, failing the current job.
+///
+/// # Returns
+///
+/// A Result containing the process group that was cancelled, or None if no build step is
+/// running. Returns an error if the group could not be signalled, e.g. because the step was not
+/// started in its own process group.
+pub fn cancel_running_group(timeout: Duration) -> Result<Option<i32>> {
+    let pgid = RUNNING_GROUP.load(Ordering::SeqCst);
+    if pgid == 0 {
+        return Ok(None);
+    }
+    info!("Cancelling running process group {}", pgid);
+    if unsafe { libc::kill(-pgid, libc::SIGTERM) } == -1 {
+        bail!(
+            "Failed to cancel process group {}: {}",
+            pgid,
+            std::io::Error::last_os_error()
+        );
+    }
+    std::thread::spawn(move || {
+        std::thread::sleep(timeout);
+        if RUNNING_GROUP.load(Ordering::SeqCst) == pgid {
+            warn!(
+                "Process group {} still running after {:?}, sending SIGKILL",
+                pgid, timeout
+            );
+            unsafe {
+                libc::kill(-pgid, libc::SIGKILL);
+            }
+        }
+    });
+    Ok(Some(pgid))
+}
+
+/// Whether a process with `pid` exists.
+pub fn is_alive(pid: i32) -> bool {
+    unsafe { libc::kill(pid, 0) == 0 }
+}
@@ -0,0 +1,496 @@
+use anyhow::{bail, Context, Result};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::config::Config;
+use crate::verify::{parse_gpg_status, SignatureStatus};
+
+/// Attestation signing cannot proceed without user interaction, e.g. because gpg-agent has no
+/// cached passphrase for the signing key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningBlocked(pub String);
+
+impl fmt::Display for SigningBlocked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Signing blocked: {}", self.0)
+    }
+}
+
+impl std::error::Error for SigningBlocked {}
+
+/// Which backend signs the SHA256SUMS files of an attestation.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum SignerConfig {
+    /// Let guix-attest sign with gpg and `gpg_key_id`.
+    #[default]
+    Gpg,
+    /// Run a shell command that reads a SHA256SUMS file on stdin and writes an ASCII-armored
+    /// detached signature to stdout.
+    Command { command: String },
+    /// Send the SHA256SUMS file to a signing service on a Unix socket and read back an
+    /// ASCII-armored detached signature.
+    Socket { path: PathBuf },
+}
+
+/// A way of producing the detached `.asc` signatures of attestation files.
+pub trait SigningBackend: Send + Sync {
+    fn describe(&self) -> String;
+
+    /// Check that the backend can sign right now without user interaction.
+    fn preflight(&self) -> Result<(), SigningBlocked>;
+
+    /// Configure the guix-attest command, which signs with gpg unless `NO_SIGN` is set.
+    fn configure_attest(&self, command: &mut Command);
+
+    /// Sign a SHA256SUMS file that guix-attest left unsigned.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the ASCII-armored detached signature.
+    fn sign(&self, file: &Path) -> Result<String>;
+}
+
+/// Create the signing backend selected in the config.
+pub fn signing_backend(config: &Config) -> Box<dyn SigningBackend> {
+    match &config.signer {
+        SignerConfig::Gpg => Box::new(GpgBackend {
+            key_id: config.gpg_key_id.clone(),
+        }),
+        SignerConfig::Command { command } => Box::new(CommandBackend {
+            command: command.clone(),
+        }),
+        SignerConfig::Socket { path } => Box::new(SocketBackend { path: path.clone() }),
+    }
+}
+
+/// Sign every `*.SHA256SUMS` file in `dir` whose `.asc` is missing or not a valid signature of
+/// the file by `gpg_key_id`.
+///
+/// The signatures returned by the backend are checked the same way before they are written, so
+/// a signer using the wrong key can't end up in a commit. The gpg backend is skipped, as
+/// guix-attest has already signed the files.
+pub fn sign_attestations(backend: &dyn SigningBackend, config: &Config, dir: &Path) -> Result<()> {
+    if config.signer == SignerConfig::Gpg {
+        return Ok(());
+    }
+    sign_files(backend, &config.gpg_key_id, dir, None)
+}
+
+/// Sign the `*.SHA256SUMS` files in `dir` as `sign_attestations` does, checking signatures with
+/// the keyring in `gnupg_home`, or the user's keyring if None.
+fn sign_files(
+    backend: &dyn SigningBackend,
+    key_id: &str,
+    dir: &Path,
+    gnupg_home: Option<&Path>,
+) -> Result<()> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory: {:?}", dir))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "SHA256SUMS"))
+        .collect();
+    files.sort();
+
+    for file in files {
+        let asc = PathBuf::from(format!("{}.asc", file.display()));
+        if let Ok(existing) = fs::read_to_string(&asc) {
+            match check_signature(&file, &existing, key_id, gnupg_home) {
+                Ok(()) => {
+                    debug!("{:?} is already signed", file);
+                    continue;
+                }
+                Err(e) => info!("Replacing signature {:?}: {:#}", asc, e),
+            }
+        }
+        info!("Signing {:?} with {}", file, backend.describe());
+        let signature = backend.sign(&file)?;
+        if !signature.starts_with("-----BEGIN PGP SIGNATURE-----") {
+            bail!(
+                "{} did not return an ASCII-armored PGP signature for {:?}",
+                backend.describe(),
+                file
+            );
+        }
+        check_signature(&file, &signature, key_id, gnupg_home).with_context(|| {
+            format!(
+                "{} returned an invalid signature for {:?}",
+                backend.describe(),
+                file
+            )
+        })?;
+        fs::write(&asc, signature)
+            .with_context(|| format!("Failed to write signature: {:?}", asc))?;
+    }
+    Ok(())
+}
+
+/// Check that `signature` is a valid detached signature of `file` by `key_id`, with the keys in
+/// `gnupg_home`, or the user's keyring if None.
+fn check_signature(
+    file: &Path,
+    signature: &str,
+    key_id: &str,
+    gnupg_home: Option<&Path>,
+) -> Result<()> {
+    let mut command = Command::new("gpg");
+    if let Some(home) = gnupg_home {
+        command.arg("--homedir").arg(home);
+    }
+    let mut child = command
+        .args(["--batch", "--status-fd", "1", "--verify", "-"])
+        .arg(file)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to execute gpg command")?;
+    // gpg stops reading early from a signature it can't parse, which its status reports
+    let _ = child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(signature.as_bytes());
+    let output = child
+        .wait_with_output()
+        .context("Failed to execute gpg command")?;
+    let status = String::from_utf8_lossy(&output.stdout);
+    match parse_gpg_status(&status) {
+        SignatureStatus::Valid => {}
+        status => bail!("signature is {}", status),
+    }
+
+    // VALIDSIG carries the fingerprint of the signing key and, last, of its primary key
+    let key_id = key_id
+        .trim_start_matches("0x")
+        .replace(' ', "")
+        .to_uppercase();
+    let signed_by_key = !key_id.is_empty()
+        && status
+            .lines()
+            .filter_map(|line| line.strip_prefix("[GNUPG:] VALIDSIG "))
+            .flat_map(|fields| {
+                let fields: Vec<&str> = fields.split(' ').collect();
+                [fields.first().copied(), fields.get(9).copied()]
+            })
+            .flatten()
+            .any(|fingerprint| fingerprint.ends_with(&key_id));
+    if !signed_by_key {
+        bail!("signature is not by gpg_key_id {}", key_id);
+    }
+    Ok(())
+}
+
+struct GpgBackend {
+    key_id: String,
+}
+
+impl SigningBackend for GpgBackend {
+    fn describe(&self) -> String {
+        format!("gpg key {}", self.key_id)
+    }
+
+    /// Signs an empty message with pinentry disabled, so a key whose passphrase is not cached by
+    /// gpg-agent fails immediately instead of waiting for a pinentry that no one will answer.
+    fn preflight(&self) -> Result<(), SigningBlocked> {
+        let key_id = self.key_id.as_str();
+        let output = Command::new("gpg")
+            .args([
+                "--batch",
+                "--no-tty",
+                "--pinentry-mode",
+                "error",
+                "--local-user",
+                key_id,
+                "--detach-sign",
+                "--output",
+                "-",
+            ])
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| SigningBlocked(format!("failed to execute gpg: {}", e)))?;
+        if output.status.success() {
+            return Ok(());
+        }
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("No secret key") || stderr.contains("unusable secret key") {
+            Err(SigningBlocked(format!(
+                "no usable secret key for {}",
+                key_id
+            )))
+        } else if stderr.contains("pinentry") || stderr.contains("passphrase") {
+            Err(SigningBlocked(format!(
+                "gpg-agent has no cached passphrase for {}. Unlock it with e.g. `echo | gpg --clearsign --local-user {}`",
+                key_id, key_id
+            )))
+        } else {
+            Err(SigningBlocked(format!(
+                "gpg could not sign with {}: {}",
+                key_id,
+                stderr.trim()
+            )))
+        }
+    }
+
+    fn configure_attest(&self, _command: &mut Command) {}
+
+    fn sign(&self, file: &Path) -> Result<String> {
+        let output = Command::new("gpg")
+            .args(["--batch", "--local-user", &self.key_id])
+            .args(["--armor", "--detach-sign", "--output", "-"])
+            .arg(file)
+            .output()
+            .context("Failed to execute gpg command")?;
+        if !output.status.success() {
+            bail!(
+                "gpg failed to sign {:?}: {}",
+                file,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+}
+
+struct CommandBackend {
+    command: String,
+}
+
+impl SigningBackend for CommandBackend {
+    fn describe(&self) -> String {
+        format!("signer command {:?}", self.command)
+    }
+
+    fn preflight(&self) -> Result<(), SigningBlocked> {
+        if self.command.trim().is_empty() {
+            return Err(SigningBlocked("signer command is empty".to_string()));
+        }
+        Ok(())
+    }
+
+    fn configure_attest(&self, command: &mut Command) {
+        command.env("NO_SIGN", "1");
+    }
+
+    fn sign(&self, file: &Path) -> Result<String> {
+        let input = fs::File::open(file).with_context(|| format!("Failed to open {:?}", file))?;
+        let output = Command::new("sh")
+            .args(["-c", &self.command])
+            .env("BGT_SIGN_FILE", file)
+            .stdin(input)
+            .output()
+            .with_context(|| format!("Failed to execute {}", self.describe()))?;
+        if !output.status.success() {
+            bail!(
+                "{} failed to sign {:?}: {}",
+                self.describe(),
+                file,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+}
+
+struct SocketBackend {
+    path: PathBuf,
+}
+
+impl SigningBackend for SocketBackend {
+    fn describe(&self) -> String {
+        format!("signing service at {:?}", self.path)
+    }
+
+    fn preflight(&self) -> Result<(), SigningBlocked> {
+        UnixStream::connect(&self.path)
+            .map(|_| ())
+            .map_err(|e| SigningBlocked(format!("cannot reach {}: {}", self.describe(), e)))
+    }
+
+    fn configure_attest(&self, command: &mut Command) {
+        command.env("NO_SIGN", "1");
+    }
+
+    /// Writes the file contents, closes the write half and reads the signature until EOF.
+    fn sign(&self, file: &Path) -> Result<String> {
+        let contents = fs::read(file).with_context(|| format!("Failed to read {:?}", file))?;
+        let mut stream = UnixStream::connect(&self.path)
+            .with_context(|| format!("Failed to connect to {}", self.describe()))?;
+        stream
+            .write_all(&contents)
+            .and_then(|_| stream.shutdown(Shutdown::Write))
+            .with_context(|| format!("Failed to send {:?} to {}", file, self.describe()))?;
+        let mut signature = String::new();
+        stream
+            .read_to_string(&mut signature)
+            .with_context(|| format!("Failed to read signature from {}", self.describe()))?;
+        Ok(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify::TempGnupgHome;
+    use std::os::unix::net::UnixListener;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bgt-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A new GnuPG home holding the keys of alice and mallory, with their fingerprints.
+    fn test_keys() -> (TempGnupgHome, String, String) {
+        let home = TempGnupgHome::create().unwrap();
+        let key = |name: &str| {
+            let uid = format!("{} <{}@example.org>", name, name);
+            let generated = Command::new("gpg")
+                .arg("--homedir")
+                .arg(home.path())
+                .args(["--batch", "--passphrase", "", "--quick-gen-key", &uid])
+                .args(["ed25519", "sign", "never"])
+                .output()
+                .unwrap();
+            assert!(generated.status.success());
+            let listed = Command::new("gpg")
+                .arg("--homedir")
+                .arg(home.path())
+                .args(["--batch", "--with-colons", "--list-keys", &uid])
+                .output()
+                .unwrap();
+            String::from_utf8_lossy(&listed.stdout)
+                .lines()
+                .find_map(|line| line.strip_prefix("fpr:"))
+                .map(|line| line.trim_matches(':').to_string())
+                .unwrap()
+        };
+        let (alice, mallory) = (key("alice"), key("mallory"));
+        (home, alice, mallory)
+    }
+
+    fn sign_command(home: &Path, key: &str) -> String {
+        format!(
+            "gpg --homedir {} --batch --armor --detach-sign --local-user {}",
+            home.display(),
+            key
+        )
+    }
+
+    #[test]
+    fn test_command_backend() {
+        let (gnupg_home, alice, mallory) = test_keys();
+        let home = Some(gnupg_home.path());
+        let dir = temp_dir("sign-command");
+        let noncodesigned = dir.join("noncodesigned.SHA256SUMS");
+        fs::write(&noncodesigned, "aaaa  bitcoin.tar.gz\n").unwrap();
+        fs::write(dir.join("all.SHA256SUMS"), "bbbb  bitcoin.tar.gz\n").unwrap();
+        let mut config = Config {
+            signer: SignerConfig::Command {
+                command: sign_command(gnupg_home.path(), &alice),
+            },
+            gpg_key_id: alice[alice.len() - 16..].to_string(),
+            ..Default::default()
+        };
+        let backend = signing_backend(&config);
+        assert!(backend.preflight().is_ok());
+        sign_files(backend.as_ref(), &config.gpg_key_id, &dir, home).unwrap();
+        let asc = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        let signature = asc("all.SHA256SUMS.asc");
+        assert!(check_signature(
+            &noncodesigned,
+            &asc("noncodesigned.SHA256SUMS.asc"),
+            &alice,
+            home
+        )
+        .is_ok());
+
+        // A signature left from before the file changed is replaced, a current one is kept
+        fs::write(&noncodesigned, "cccc  bitcoin.tar.gz\n").unwrap();
+        let stale = asc("noncodesigned.SHA256SUMS.asc");
+        assert!(check_signature(&noncodesigned, &stale, &alice, home).is_err());
+        sign_files(backend.as_ref(), &config.gpg_key_id, &dir, home).unwrap();
+        assert!(check_signature(
+            &noncodesigned,
+            &asc("noncodesigned.SHA256SUMS.asc"),
+            &alice,
+            home
+        )
+        .is_ok());
+        assert_eq!(asc("all.SHA256SUMS.asc"), signature);
+
+        // A signer using a different key than gpg_key_id is rejected, keeping the old signature
+        let previous = asc("noncodesigned.SHA256SUMS.asc");
+        fs::write(&noncodesigned, "dddd  bitcoin.tar.gz\n").unwrap();
+        config.signer = SignerConfig::Command {
+            command: sign_command(gnupg_home.path(), &mallory),
+        };
+        let backend = signing_backend(&config);
+        let result = sign_files(backend.as_ref(), &config.gpg_key_id, &dir, home);
+        let kept = asc("noncodesigned.SHA256SUMS.asc");
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(format!("{:#}", result.unwrap_err()).contains("not by gpg_key_id"));
+        assert_eq!(kept, previous);
+    }
+
+    #[test]
+    fn test_socket_backend() {
+        let (gnupg_home, alice, _) = test_keys();
+        let home = Some(gnupg_home.path());
+        let dir = temp_dir("sign-socket");
+        let socket = dir.join("signer.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let command = sign_command(gnupg_home.path(), &alice);
+        let stub = std::thread::spawn(move || {
+            // One connection for the preflight, one for the signature
+            listener.accept().unwrap();
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            stream.read_to_string(&mut request).unwrap();
+            let mut gpg = Command::new("sh")
+                .args(["-c", &command])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+            gpg.stdin
+                .take()
+                .unwrap()
+                .write_all(request.as_bytes())
+                .unwrap();
+            stream
+                .write_all(&gpg.wait_with_output().unwrap().stdout)
+                .unwrap();
+            request
+        });
+
+        let config = Config {
+            signer: SignerConfig::Socket {
+                path: socket.clone(),
+            },
+            gpg_key_id: alice.clone(),
+            ..Default::default()
+        };
+        let file = dir.join("noncodesigned.SHA256SUMS");
+        fs::write(&file, "aaaa  bitcoin.tar.gz\n").unwrap();
+        let backend = signing_backend(&config);
+        backend.preflight().unwrap();
+        sign_files(backend.as_ref(), &config.gpg_key_id, &dir, home).unwrap();
+
+        let request = stub.join().unwrap();
+        let signature = fs::read_to_string(dir.join("noncodesigned.SHA256SUMS.asc")).unwrap();
+        let checked = check_signature(&file, &signature, &alice, home);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(request, "aaaa  bitcoin.tar.gz\n");
+        assert!(checked.is_ok());
+    }
+}
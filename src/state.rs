@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+/// The last completed stage of the watcher pipeline for a tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stage {
+    /// Picked up by the watcher, with no stage completed yet.
+    Started,
+    Built,
+    NonCodesignedAttested,
+    CodesignedAttested,
+    PrOpened,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Started => write!(f, "started"),
+            Stage::Built => write!(f, "built"),
+            Stage::NonCodesignedAttested => write!(f, "non-codesigned attested"),
+            Stage::CodesignedAttested => write!(f, "codesigned attested"),
+            Stage::PrOpened => write!(f, "PR opened"),
+        }
+    }
+}
+
+/// Pipeline stage of every tag the watcher has worked on, persisted to a state file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct JobState {
+    #[serde(skip)]
+    path: PathBuf,
+    tags: BTreeMap<String, Stage>,
+    #[serde(default)]
+    filtered: BTreeMap<String, String>,
+    #[serde(default)]
+    blocked: BTreeMap<String, String>,
+}
+
+impl JobState {
+    /// Load the job state from `path`, starting empty if the file does not exist.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let mut state: JobState = if path.exists() {
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read state file: {:?}", path))?;
+            serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse state file: {:?}", path))?
+        } else {
+            debug!("No state file found at {:?}, starting fresh", path);
+            JobState::default()
+        };
+        state.path = path;
+        Ok(state)
+    }
+
+    /// Write the job state to disk, replacing the previous file atomically.
+    pub fn save(&self) -> Result<()> {
+        let contents =
+            serde_json::to_string_pretty(self).context("Failed to serialize job state")?;
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, contents)
+            .with_context(|| format!("Failed to write state file: {:?}", tmp))?;
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace state file: {:?}", self.path))?;
+        Ok(())
+    }
+
+    pub fn stage(&self, tag: &str) -> Option<Stage> {
+        self.tags.get(tag).copied()
+    }
+
+    /// Record that `tag` has completed `stage` and persist the change.
+    pub fn set_stage(&mut self, tag: &str, stage: Stage) -> Result<()> {
+        debug!("Tag {} reached stage: {}", tag, stage);
+        self.tags.insert(tag.to_string(), stage);
+        self.save()
+    }
+
+    /// Record that `tag` was skipped by the tag filter and persist the change.
+    pub fn set_filtered(&mut self, tag: &str, reason: &str) -> Result<()> {
+        self.filtered.insert(tag.to_string(), reason.to_string());
+        self.save()
+    }
+
+    /// Record that the next stage of `tag` is blocked on signing and persist the change.
+    pub fn set_blocked(&mut self, tag: &str, reason: &str) -> Result<()> {
+        self.blocked.insert(tag.to_string(), reason.to_string());
+        self.save()
+    }
+
+    /// Clear a blocked status of `tag`, persisting the change if there was one.
+    pub fn clear_blocked(&mut self, tag: &str) -> Result<()> {
+        if self.blocked.remove(tag).is_some() {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    pub fn is_blocked(&self) -> bool {
+        !self.blocked.is_empty()
+    }
+
+    /// Tags that have been started but not yet codesigned.
+    pub fn awaiting_codesign(&self) -> Vec<String> {
+        self.tags
+            .iter()
+            .filter(|(_, stage)| **stage < Stage::CodesignedAttested)
+            .map(|(tag, _)| tag.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_state_roundtrip() {
+        let path = std::env::temp_dir().join(format!("bgt-state-{}.json", std::process::id()));
+        let mut state = JobState::load(path.clone()).unwrap();
+        state.set_stage("v27.0", Stage::PrOpened).unwrap();
+        state.set_stage("v28.0rc1", Stage::Built).unwrap();
+        state.set_stage("v28.1", Stage::Started).unwrap();
+        state
+            .set_stage("v28.0", Stage::NonCodesignedAttested)
+            .unwrap();
+
+        let loaded = JobState::load(path.clone()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.stage("v27.0"), Some(Stage::PrOpened));
+        assert_eq!(loaded.stage("v29.0"), None);
+        assert_eq!(
+            loaded.awaiting_codesign(),
+            vec!["v28.0", "v28.0rc1", "v28.1"]
+        );
+    }
+}
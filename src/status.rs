@@ -0,0 +1,220 @@
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The step the watcher is currently running for a tag.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CurrentJob {
+    pub tag: String,
+    pub step: String,
+    pub started_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastError {
+    pub at: u64,
+    pub message: String,
+}
+
+/// What the watcher is doing, published to a status file for `bgt watch status`.
+///
+/// Timestamps are seconds since the Unix epoch.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WatchStatus {
+    #[serde(skip)]
+    path: PathBuf,
+    pub pid: u32,
+    pub started_at: u64,
+    pub last_poll: Option<u64>,
+    #[serde(default)]
+    pub paused: bool,
+    pub current_job: Option<CurrentJob>,
+    pub queue: Vec<String>,
+    pub last_error: Option<LastError>,
+}
+
+impl WatchStatus {
+    /// Start a fresh status for this watcher process and write it to `path`.
+    pub fn start(path: PathBuf) -> Result<Self> {
+        let status = Self {
+            path,
+            pid: std::process::id(),
+            started_at: now(),
+            ..Default::default()
+        };
+        status.save()?;
+        Ok(status)
+    }
+
+    /// Load the status last published by a watcher, if any.
+    pub fn load(path: PathBuf) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read status file: {:?}", path))?;
+        let mut status: WatchStatus = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse status file: {:?}", path))?;
+        status.path = path;
+        Ok(Some(status))
+    }
+
+    fn save(&self) -> Result<()> {
+        let contents =
+            serde_json::to_string_pretty(self).context("Failed to serialize watcher status")?;
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, contents)
+            .with_context(|| format!("Failed to write status file: {:?}", tmp))?;
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace status file: {:?}", self.path))
+    }
+
+    /// Save the status after a change, warning rather than failing, as the status is only
+    /// informational and must not stop the watcher.
+    fn publish(&self) {
+        if let Err(e) = self.save() {
+            warn!("Failed to publish watcher status: {:#}", e);
+        }
+    }
+
+    pub fn record_poll(&mut self) {
+        self.last_poll = Some(now());
+        self.publish()
+    }
+
+    pub fn record_error(&mut self, error: &anyhow::Error) {
+        self.last_error = Some(LastError {
+            at: now(),
+            message: format!("{:#}", error),
+        });
+        self.publish()
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        if self.paused == paused {
+            return;
+        }
+        self.paused = paused;
+        self.publish()
+    }
+
+    pub fn set_queue(&mut self, tags: Vec<String>) {
+        self.queue = tags;
+        self.publish()
+    }
+
+    /// Mark `step` of `tag` as the current job, removing the tag from the queue.
+    pub fn start_job(&mut self, tag: &str, step: &str) {
+        self.queue.retain(|t| t != tag);
+        self.current_job = Some(CurrentJob {
+            tag: tag.to_string(),
+            step: step.to_string(),
+            started_at: now(),
+        });
+        self.publish()
+    }
+
+    pub fn finish_job(&mut self) {
+        self.current_job = None;
+        self.publish()
+    }
+
+    /// Clear what a watcher that is no longer running was doing, which is stale, keeping its
+    /// last poll and error.
+    pub fn clear_activity(&mut self) {
+        self.paused = false;
+        self.current_job = None;
+        self.queue.clear();
+    }
+}
+
+impl fmt::Display for WatchStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<16} {}", "Started:", format_age(self.started_at))?;
+        writeln!(
+            f,
+            "{:<16} {}",
+            "Last poll:",
+            self.last_poll.map_or("never".to_string(), format_age)
+        )?;
+        if self.paused {
+            writeln!(f, "{:<16} yes", "Paused:")?;
+        }
+        match &self.current_job {
+            Some(job) => writeln!(
+                f,
+                "{:<16} {} {} (since {})",
+                "Current job:",
+                job.tag,
+                job.step,
+                format_age(job.started_at)
+            )?,
+            None => writeln!(f, "{:<16} idle", "Current job:")?,
+        }
+        writeln!(
+            f,
+            "{:<16} {}",
+            "Queue:",
+            if self.queue.is_empty() {
+                "empty".to_string()
+            } else {
+                self.queue.join(", ")
+            }
+        )?;
+        match &self.last_error {
+            Some(error) => write!(
+                f,
+                "{:<16} {} ({})",
+                "Last error:",
+                error.message,
+                format_age(error.at)
+            ),
+            None => write!(f, "{:<16} none", "Last error:"),
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Format a Unix timestamp relative to now, e.g. "5m 02s ago".
+fn format_age(timestamp: u64) -> String {
+    let secs = now().saturating_sub(timestamp);
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m {:02}s ago", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m ago", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watch_status_roundtrip() {
+        let path = std::env::temp_dir().join(format!("bgt-status-{}.json", std::process::id()));
+        let mut status = WatchStatus::start(path.clone()).unwrap();
+        status.set_queue(vec!["v28.0".to_string(), "v28.1".to_string()]);
+        status.start_job("v28.0", "build");
+        status.record_error(&anyhow::anyhow!("guix-build failed"));
+
+        let mut loaded = WatchStatus::load(path.clone()).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.pid, std::process::id());
+        assert_eq!(loaded.queue, vec!["v28.1"]);
+        assert_eq!(loaded.current_job.as_ref().unwrap().step, "build");
+        loaded.clear_activity();
+        assert!(loaded.queue.is_empty() && loaded.current_job.is_none());
+        assert_eq!(loaded.last_error.unwrap().message, "guix-build failed");
+        assert_eq!(format_age(now() - 3725), "1h 02m ago");
+    }
+}
@@ -0,0 +1,267 @@
+use anyhow::{bail, Context, Result};
+use std::ffi::{CString, OsString};
+use std::fmt;
+use std::fs;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The outcome of verifying a single detached signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureStatus {
+    Valid,
+    Expired,
+    Bad,
+    UnknownKey,
+    Error(String),
+}
+
+impl fmt::Display for SignatureStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureStatus::Valid => write!(f, "valid"),
+            SignatureStatus::Expired => write!(f, "expired"),
+            SignatureStatus::Bad => write!(f, "BAD"),
+            SignatureStatus::UnknownKey => write!(f, "unknown key"),
+            SignatureStatus::Error(e) => write!(f, "error: {}", e),
+        }
+    }
+}
+
+/// Verification results for every `*.SHA256SUMS.asc` file of one signer.
+#[derive(Debug, Clone)]
+pub struct SignerVerification {
+    pub signer: String,
+    pub files: Vec<(String, SignatureStatus)>,
+}
+
+impl SignerVerification {
+    pub fn is_valid(&self) -> bool {
+        !self.files.is_empty()
+            && self
+                .files
+                .iter()
+                .all(|(_, status)| *status == SignatureStatus::Valid)
+    }
+}
+
+/// Verification results for every signer of a version in guix.sigs.
+#[derive(Debug, Clone)]
+pub struct VerifyReport {
+    pub version: String,
+    pub signers: Vec<SignerVerification>,
+}
+
+impl VerifyReport {
+    pub fn valid_count(&self) -> usize {
+        self.signers.iter().filter(|s| s.is_valid()).count()
+    }
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Signatures for {}:", self.version)?;
+        for signer in &self.signers {
+            for (file, status) in &signer.files {
+                writeln!(f, "  {:<24} {:<36} {}", signer.signer, file, status)?;
+            }
+            if signer.files.is_empty() {
+                writeln!(f, "  {:<24} {:<36} no signatures", signer.signer, "")?;
+            }
+        }
+        writeln!(
+            f,
+            "{} of {} signers have only valid signatures",
+            self.valid_count(),
+            self.signers.len()
+        )
+    }
+}
+
+/// Verify the GPG signatures of every signer of `version` in the guix.sigs checkout.
+///
+/// Each signer's signatures are checked in a throwaway GnuPG home containing only the
+/// signer's key from `builder-keys/<signer>.gpg`, so the user's keyring is never touched.
+///
+/// # Returns
+///
+/// A Result containing a VerifyReport, or an error if the version directory could not be read.
+pub fn verify_signatures(guix_sigs_dir: &Path, version: &str) -> Result<VerifyReport> {
+    let version_dir = guix_sigs_dir.join(version);
+    if !version_dir.is_dir() {
+        bail!(
+            "No attestations found for {} in {:?}",
+            version,
+            guix_sigs_dir
+        );
+    }
+
+    let mut signers: Vec<String> = fs::read_dir(&version_dir)
+        .with_context(|| format!("Failed to read directory: {:?}", version_dir))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    signers.sort();
+
+    let mut results = Vec::new();
+    for signer in signers {
+        let key_file = guix_sigs_dir
+            .join("builder-keys")
+            .join(format!("{}.gpg", signer));
+        let gnupg_home = TempGnupgHome::create()?;
+        let files = verify_signer(&version_dir.join(&signer), &key_file, gnupg_home.path())?;
+        results.push(SignerVerification { signer, files });
+    }
+
+    Ok(VerifyReport {
+        version: version.to_string(),
+        signers: results,
+    })
+}
+
+/// A new, private GnuPG home directory, removed when dropped.
+///
+/// The directory is created by mkdtemp with an unpredictable name and mode 0700, so another
+/// user can't plant a keyring in it.
+pub(crate) struct TempGnupgHome(PathBuf);
+
+impl TempGnupgHome {
+    pub fn create() -> Result<Self> {
+        let template = std::env::temp_dir().join("bgt-gnupg-XXXXXX");
+        let mut template = CString::new(template.as_os_str().as_bytes())
+            .context("Invalid temporary directory path")?
+            .into_bytes_with_nul();
+        let dir = unsafe { libc::mkdtemp(template.as_mut_ptr() as *mut libc::c_char) };
+        if dir.is_null() {
+            return Err(std::io::Error::last_os_error())
+                .context("Failed to create temporary GnuPG home");
+        }
+        template.pop();
+        Ok(Self(PathBuf::from(OsString::from_vec(template))))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempGnupgHome {
+    /// Stops the gpg-agent gpg may have started for the home before removing it.
+    fn drop(&mut self) {
+        if self.0.join("S.gpg-agent").exists() {
+            let _ = Command::new("gpgconf")
+                .arg("--homedir")
+                .arg(&self.0)
+                .args(["--kill", "gpg-agent"])
+                .output();
+        }
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn verify_signer(
+    signer_dir: &Path,
+    key_file: &PathBuf,
+    gnupg_home: &Path,
+) -> Result<Vec<(String, SignatureStatus)>> {
+    let mut asc_files: Vec<PathBuf> = fs::read_dir(signer_dir)
+        .with_context(|| format!("Failed to read directory: {:?}", signer_dir))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.ends_with(".SHA256SUMS.asc"))
+        })
+        .collect();
+    asc_files.sort();
+
+    let key_imported = key_file.exists()
+        && Command::new("gpg")
+            .arg("--homedir")
+            .arg(gnupg_home)
+            .args(["--batch", "--quiet", "--import"])
+            .arg(key_file)
+            .output()
+            .context("Failed to execute gpg command")?
+            .status
+            .success();
+
+    let mut files = Vec::new();
+    for asc in asc_files {
+        let name = asc.file_name().unwrap().to_string_lossy().to_string();
+        let status = if key_imported {
+            let output = Command::new("gpg")
+                .arg("--homedir")
+                .arg(gnupg_home)
+                .args(["--batch", "--status-fd", "1", "--verify"])
+                .arg(&asc)
+                .arg(asc.with_extension(""))
+                .output()
+                .context("Failed to execute gpg command")?;
+            parse_gpg_status(&String::from_utf8_lossy(&output.stdout))
+        } else {
+            SignatureStatus::UnknownKey
+        };
+        files.push((name, status));
+    }
+    Ok(files)
+}
+
+/// Interpret the `--status-fd` output of `gpg --verify`.
+pub(crate) fn parse_gpg_status(status: &str) -> SignatureStatus {
+    let keywords: Vec<&str> = status
+        .lines()
+        .filter_map(|line| line.strip_prefix("[GNUPG:] "))
+        .filter_map(|line| line.split_whitespace().next())
+        .collect();
+
+    if keywords.contains(&"BADSIG") || keywords.contains(&"REVKEYSIG") {
+        SignatureStatus::Bad
+    } else if keywords.contains(&"EXPSIG") || keywords.contains(&"EXPKEYSIG") {
+        SignatureStatus::Expired
+    } else if keywords.contains(&"NO_PUBKEY") {
+        SignatureStatus::UnknownKey
+    } else if keywords.contains(&"GOODSIG") && keywords.contains(&"VALIDSIG") {
+        SignatureStatus::Valid
+    } else if keywords.contains(&"ERRSIG") {
+        SignatureStatus::Error("signature could not be checked".to_string())
+    } else {
+        SignatureStatus::Error("no signature found".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temp_gnupg_home() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let home = TempGnupgHome::create().unwrap();
+        let other = TempGnupgHome::create().unwrap();
+        let path = home.path().to_path_buf();
+        assert_ne!(path, other.path());
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o700
+        );
+        drop(home);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_parse_gpg_status() {
+        let good =
+            "[GNUPG:] NEWSIG\n[GNUPG:] GOODSIG ABCD alice\n[GNUPG:] VALIDSIG ABCD 2024-01-01\n";
+        assert_eq!(parse_gpg_status(good), SignatureStatus::Valid);
+        let expired = "[GNUPG:] NEWSIG\n[GNUPG:] EXPKEYSIG ABCD alice\n[GNUPG:] VALIDSIG ABCD\n";
+        assert_eq!(parse_gpg_status(expired), SignatureStatus::Expired);
+        let bad = "[GNUPG:] NEWSIG\n[GNUPG:] BADSIG ABCD alice\n";
+        assert_eq!(parse_gpg_status(bad), SignatureStatus::Bad);
+        let unknown = "[GNUPG:] NEWSIG\n[GNUPG:] ERRSIG ABCD 1 8 00 1700000000 9 -\n[GNUPG:] NO_PUBKEY ABCD\n";
+        assert_eq!(parse_gpg_status(unknown), SignatureStatus::UnknownKey);
+    }
+}
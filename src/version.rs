@@ -1,62 +1,159 @@
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
 
-/// Parse a version string into a vector of integers and a release candidate number.
+/// A Bitcoin Core release version parsed from a tag.
 ///
-/// # Arguments
+/// Covers both the old `v0.x.y` and the new `vNN.x[.y]` numbering schemes, release candidates
+/// (`v28.0rc1`) and tags with a trailing suffix (`v0.21.0-final`). Old-scheme versions always
+/// sort before new-scheme ones as their major version is 0.
 ///
-/// * `v` - A version string (e.g., "v0.21.0", "22.0", or "v0.28.0rc1")
-///
-/// # Returns
-///
-/// A tuple containing:
-/// - A vector of integers representing the version components
-/// - An Option<u32> representing the release candidate number (if present)
-fn parse_version(v: &str) -> (Vec<u32>, Option<u32>) {
-    let v = v.trim_start_matches('v');
-    let parts: Vec<&str> = v.split("rc").collect();
+/// A missing patch component compares equal to a patch of 0, so `v22.0 == v22.0.0`.
+#[derive(Debug, Clone)]
+pub struct BitcoinVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: Option<u32>,
+    pub rc: Option<u32>,
+    pub suffix: Option<String>,
+}
+
+/// An error returned when a tag is not a valid Bitcoin Core version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseVersionError {
+    /// The tag does not have the form `v<major>.<minor>[.<patch>][rc<n>][-<suffix>]`.
+    InvalidFormat(String),
+    /// A numeric component does not fit in a u32.
+    InvalidNumber(String),
+}
+
+impl fmt::Display for ParseVersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseVersionError::InvalidFormat(s) => {
+                write!(f, "{:?} is not a valid Bitcoin Core version", s)
+            }
+            ParseVersionError::InvalidNumber(s) => {
+                write!(f, "{:?} contains an out of range version number", s)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseVersionError {}
+
+impl BitcoinVersion {
+    /// The name of this version's directory in guix.sigs and in guix-build output paths.
+    pub fn dir_name(&self) -> String {
+        self.to_string()[1..].to_string()
+    }
+
+    pub fn is_rc(&self) -> bool {
+        self.rc.is_some()
+    }
+}
 
-    let version_parts = parts[0]
-        .split('.')
-        .map(|s| s.parse().unwrap_or(0))
-        .collect();
+impl FromStr for BitcoinVersion {
+    type Err = ParseVersionError;
 
-    let rc = parts.get(1).and_then(|&s| s.parse().ok());
+    /// Parse a version string (e.g., "v0.21.0", "22.0", "v28.0rc1" or "v0.21.0-final").
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        static RE: OnceLock<Regex> = OnceLock::new();
+        let re = RE.get_or_init(|| {
+            Regex::new(r"^v?(\d+)\.(\d+)(?:\.(\d+))?(?:rc(\d+))?(?:-(.+))?$")
+                .expect("version regex is valid")
+        });
+        let captures = re
+            .captures(s)
+            .ok_or_else(|| ParseVersionError::InvalidFormat(s.to_string()))?;
+        let number = |i: usize| -> Result<Option<u32>, ParseVersionError> {
+            captures
+                .get(i)
+                .map(|m| m.as_str().parse::<u32>())
+                .transpose()
+                .map_err(|_| ParseVersionError::InvalidNumber(s.to_string()))
+        };
 
-    (version_parts, rc)
+        Ok(Self {
+            major: number(1)?.unwrap_or_default(),
+            minor: number(2)?.unwrap_or_default(),
+            patch: number(3)?,
+            rc: number(4)?,
+            suffix: captures.get(5).map(|m| m.as_str().to_string()),
+        })
+    }
 }
 
-/// Compares two version strings.
-///
-/// # Arguments
-///
-/// * `a` - First version string
-/// * `b` - Second version string
-///
-/// # Returns
-///
-/// An Ordering indicating the relationship between the two versions.
-pub fn compare_versions(a: &str, b: &str) -> Ordering {
-    let (va, rc_a) = parse_version(a);
-    let (vb, rc_b) = parse_version(b);
-
-    // Compare the first two components of the version numbers
-    match va.iter().take(2).cmp(vb.iter().take(2)) {
-        Ordering::Equal => {
-            // If the first two components are equal, compare the third component
-            match va.get(2).cmp(&vb.get(2)) {
-                Ordering::Equal => {
-                    // If all version components are equal, compare rc numbers
-                    match (rc_a, rc_b) {
-                        (None, None) => Ordering::Equal,
-                        (Some(_), None) => Ordering::Less,
-                        (None, Some(_)) => Ordering::Greater,
-                        (Some(a), Some(b)) => a.cmp(&b),
-                    }
-                }
-                other => other,
-            }
+impl fmt::Display for BitcoinVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}.{}", self.major, self.minor)?;
+        if let Some(patch) = self.patch {
+            write!(f, ".{}", patch)?;
         }
-        other => other,
+        if let Some(rc) = self.rc {
+            write!(f, "rc{}", rc)?;
+        }
+        if let Some(suffix) = &self.suffix {
+            write!(f, "-{}", suffix)?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for BitcoinVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch.unwrap_or(0))
+            .cmp(&(other.major, other.minor, other.patch.unwrap_or(0)))
+            .then_with(|| match (self.rc, other.rc) {
+                // A release candidate comes before the final release
+                (None, None) => Ordering::Equal,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(a), Some(b)) => a.cmp(&b),
+            })
+            .then_with(|| self.suffix.cmp(&other.suffix))
+    }
+}
+
+impl PartialOrd for BitcoinVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for BitcoinVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for BitcoinVersion {}
+
+impl Serialize for BitcoinVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for BitcoinVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Compares two tags, ordering valid versions after any tags that are not versions.
+///
+/// Tags that are not valid versions are ordered by name.
+pub fn compare_tags(a: &str, b: &str) -> Ordering {
+    match (a.parse::<BitcoinVersion>(), b.parse::<BitcoinVersion>()) {
+        (Ok(va), Ok(vb)) => va.cmp(&vb),
+        (Ok(_), Err(_)) => Ordering::Greater,
+        (Err(_), Ok(_)) => Ordering::Less,
+        (Err(_), Err(_)) => a.cmp(b),
     }
 }
 
@@ -64,16 +161,46 @@ pub fn compare_versions(a: &str, b: &str) -> Ordering {
 mod tests {
     use super::*;
 
+    fn v(s: &str) -> BitcoinVersion {
+        s.parse().unwrap()
+    }
+
     #[test]
     fn test_compare_versions() {
-        assert_eq!(compare_versions("v0.21.0", "v0.28.0rc1"), Ordering::Less);
-        assert_eq!(compare_versions("v0.28.0rc1", "v0.28.0"), Ordering::Less);
+        assert!(v("v0.21.0") < v("v0.28.0rc1"));
+        assert!(v("v0.28.0rc1") < v("v0.28.0"));
+        assert!(v("v0.28.0rc2") > v("v0.28.0rc1"));
+        assert_eq!(v("v0.28.0"), v("v0.28.0"));
+        assert!(v("v1.0.0") > v("v0.28.0"));
+        assert!(v("v0.28.1") > v("v0.28.0rc1"));
+        assert!(v("v0.21.2") < v("v22.0"));
+        assert!(v("v27.2") > v("v27.1"));
+        assert_eq!(v("v22.0"), v("v22.0.0"));
+    }
+
+    #[test]
+    fn test_parse_version() {
+        let version = v("v28.0rc1-foo");
+        assert_eq!((version.major, version.minor), (28, 0));
+        assert_eq!((version.patch, version.rc), (None, Some(1)));
+        assert_eq!(version.suffix.as_deref(), Some("foo"));
+        assert_eq!(version.to_string(), "v28.0rc1-foo");
+
+        let version = v("v0.21.0-final");
         assert_eq!(
-            compare_versions("v0.28.0rc2", "v0.28.0rc1"),
-            Ordering::Greater
+            (version.major, version.minor, version.patch),
+            (0, 21, Some(0))
         );
-        assert_eq!(compare_versions("v0.28.0", "v0.28.0"), Ordering::Equal);
-        assert_eq!(compare_versions("v1.0.0", "v0.28.0"), Ordering::Greater);
-        assert_eq!(compare_versions("v0.28.1", "v0.28.0rc1"), Ordering::Greater);
+        assert_eq!(version.dir_name(), "0.21.0-final");
+        assert_eq!(v("22.0").to_string(), "v22.0");
+
+        assert!(matches!(
+            "noversion".parse::<BitcoinVersion>(),
+            Err(ParseVersionError::InvalidFormat(_))
+        ));
+        assert!(matches!(
+            "v99999999999.0".parse::<BitcoinVersion>(),
+            Err(ParseVersionError::InvalidNumber(_))
+        ));
     }
 }
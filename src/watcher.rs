@@ -1,124 +1,467 @@
 use std::collections::HashSet;
+use std::fs;
+use std::sync::Arc;
 
 use crate::builder::{BuildAction, BuildArgs};
 use crate::commands::create_builder;
-use crate::config::Config;
+use crate::config::{get_config_file, Config, ConfigOverrides};
+use crate::control::{spawn_control_server, Control};
+use crate::process::{manage_child_groups, request_shutdown, shutdown_requested};
+use crate::signing::{signing_backend, SigningBlocked};
+use crate::state::{JobState, Stage};
+use crate::status::WatchStatus;
+use crate::version::BitcoinVersion;
 use anyhow::{Context, Result};
-use log::{debug, error, info, warn};
+use log::{debug, error, info};
 use tokio::signal;
 use tokio::signal::unix::{signal, SignalKind};
-use tokio::time::sleep;
+use tokio::sync::Notify;
+use tokio::time::{sleep_until, Instant};
 
-use crate::fetcher::check_for_new_tags;
+use crate::fetcher::{check_for_new_tags, TagSources};
 
 pub(crate) async fn run_watcher(
     config: &Config,
+    overrides: &ConfigOverrides,
+    mut sources: TagSources,
     seen_tags_bitcoin: &mut HashSet<String>,
     seen_tags_sigs: &mut HashSet<String>,
+    auto: bool,
     dry_run: bool,
 ) -> Result<()> {
-    let mut in_progress: HashSet<String> = HashSet::new();
+    let mut config = config.clone();
+    let control = Arc::new(Control::new(config.stop_timeout));
+    // Before any build step runs, so every step gets its own process group that a stop cancels
+    let shutdown = Arc::new(Notify::new());
+    spawn_shutdown_handler(control.clone(), shutdown.clone())?;
+    let mut state = JobState::load(get_config_file("watch_state.json"))
+        .context("Failed to load watcher job state")?;
+    let mut status = WatchStatus::start(get_config_file("watch_status.json"))
+        .context("Failed to publish watcher status")?;
+    let control_socket = get_config_file("watch.sock");
+    spawn_control_server(&control_socket, control.clone(), overrides.clone())?;
+    if let Err(e) = resume_jobs(
+        &config,
+        seen_tags_sigs,
+        &mut state,
+        &mut status,
+        auto,
+        dry_run,
+    )
+    .await
+    {
+        error!("Error resuming jobs: {:?}", e);
+        status.record_error(&e);
+    }
     info!(
-        "Polling {}/{} and {}/{} for new tags every {:?}...",
-        config.source_repo_owner,
-        config.source_repo_name,
-        config.detached_repo_owner,
-        config.detached_repo_name,
+        "Polling {} and {} for new tags every {:?}...",
+        sources.bitcoin.describe(),
+        sources.sigs.describe(),
         config.poll_interval
     );
-    let mut sigterm =
-        signal(SignalKind::terminate()).context("Failed to register SIGTERM handler")?;
 
+    let mut next_poll = Instant::now() + config.poll_interval;
     loop {
         tokio::select! {
-            _ = sleep(config.poll_interval) => {
-                if let Err(e) = check_and_process_bitcoin_tags(config, seen_tags_bitcoin, &mut in_progress, dry_run).await {
-                    error!("Error processing Bitcoin tags: {:?}", e);
+            _ = sleep_until(next_poll) => {
+                next_poll = Instant::now() + config.poll_interval;
+                control.request_poll();
+            }
+            _ = control.notified() => {}
+            _ = shutdown.notified() => break,
+        }
+        if let Some(reloaded) = control.take_reloaded() {
+            match TagSources::from_config(&reloaded) {
+                Ok(reloaded_sources) => {
+                    info!("Applying reloaded config");
+                    sources = reloaded_sources;
+                    config = reloaded;
                 }
-                if let Err(e) = check_and_process_sigs_tags(config, seen_tags_sigs, &mut in_progress, dry_run).await {
-                    error!("Error processing sigs tags: {:?}", e);
+                Err(e) => {
+                    error!("Error applying reloaded config: {:?}", e);
+                    status.record_error(&e);
                 }
             }
-            _ = signal::ctrl_c() => {
-                info!("Received Ctrl+C. Shutting down...");
-                break;
+        }
+        status.set_paused(control.is_paused());
+        if control.is_paused() {
+            continue;
+        }
+        if control.take_poll_request() {
+            status.record_poll();
+            if let Err(e) = retry_blocked_jobs(
+                &config,
+                seen_tags_sigs,
+                &mut state,
+                &mut status,
+                auto,
+                dry_run,
+            )
+            .await
+            {
+                error!("Error retrying jobs blocked on signing: {:?}", e);
+                status.record_error(&e);
+            }
+            if let Err(e) = check_and_process_bitcoin_tags(
+                &config,
+                &sources,
+                seen_tags_bitcoin,
+                &mut state,
+                &mut status,
+                auto,
+                dry_run,
+            )
+            .await
+            {
+                error!("Error processing Bitcoin tags: {:?}", e);
+                status.record_error(&e);
             }
-            _ = sigterm.recv() => {
-                info!("Received SIGTERM. Shutting down...");
-                break;
+            if let Err(e) = check_and_process_sigs_tags(
+                &config,
+                &sources,
+                seen_tags_sigs,
+                &mut state,
+                &mut status,
+                auto,
+                dry_run,
+            )
+            .await
+            {
+                error!("Error processing sigs tags: {:?}", e);
+                status.record_error(&e);
             }
         }
+        if let Err(e) = process_queued_tags(
+            &config,
+            &control,
+            seen_tags_sigs,
+            &mut state,
+            &mut status,
+            auto,
+            dry_run,
+        )
+        .await
+        {
+            error!("Error processing queued tags: {:?}", e);
+            status.record_error(&e);
+        }
+        if shutdown_requested() {
+            break;
+        }
+    }
+    if let Err(e) = fs::remove_file(&control_socket) {
+        debug!(
+            "Failed to remove control socket {:?}: {}",
+            control_socket, e
+        );
     }
     info!("Watcher stopped.");
     Ok(())
 }
 
+/// Listen for Ctrl+C and SIGTERM, cancelling the running build step and waking the watcher loop.
+///
+/// Build steps block the thread they run on, so the signals are handled on a separate task.
+fn spawn_shutdown_handler(control: Arc<Control>, shutdown: Arc<Notify>) -> Result<()> {
+    let mut sigterm =
+        signal(SignalKind::terminate()).context("Failed to register SIGTERM handler")?;
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = signal::ctrl_c() => info!("Received Ctrl+C. Shutting down..."),
+            _ = sigterm.recv() => info!("Received SIGTERM. Shutting down..."),
+        }
+        request_shutdown(control.stop_timeout());
+        shutdown.notify_one();
+    });
+    manage_child_groups();
+    Ok(())
+}
+
+/// Resume every tag recorded in the job state from its last completed stage.
+///
+/// A tag that fails is logged and recorded in the status, and the remaining tags are still
+/// resumed.
+async fn resume_jobs(
+    config: &Config,
+    seen_tags_sigs: &HashSet<String>,
+    state: &mut JobState,
+    status: &mut WatchStatus,
+    auto: bool,
+    dry_run: bool,
+) -> Result<()> {
+    // Attested tags only have a stage left to run once their detached sigs are published
+    let tags: Vec<String> = state
+        .awaiting_codesign()
+        .into_iter()
+        .filter(|tag| {
+            state.stage(tag) < Some(Stage::NonCodesignedAttested) || seen_tags_sigs.contains(tag)
+        })
+        .collect();
+    status.set_queue(tags.clone());
+    for tag in tags {
+        if shutdown_requested() {
+            break;
+        }
+        info!("Resuming tag {} from stage: {:?}", tag, state.stage(&tag));
+        if dry_run {
+            info!("Skipping resume for tag {tag} because --dry-run is enabled");
+            continue;
+        }
+        if let Err(e) = continue_tag(config, &tag, seen_tags_sigs, state, status, auto).await {
+            error!("Error resuming tag {}: {:?}", tag, e);
+            status.record_error(&e);
+        }
+    }
+    Ok(())
+}
+
+/// Run the remaining stages of `tag` after its last completed stage, codesigning only once its
+/// detached sigs have been published.
+async fn continue_tag(
+    config: &Config,
+    tag: &str,
+    seen_tags_sigs: &HashSet<String>,
+    state: &mut JobState,
+    status: &mut WatchStatus,
+    auto: bool,
+) -> Result<()> {
+    let version: BitcoinVersion = tag.parse()?;
+    if state.stage(tag) < Some(Stage::Built) {
+        build(config, &version, state, status, auto).await?;
+    }
+    if state.stage(tag) == Some(Stage::Built) {
+        attest_noncodesigned(config, &version, state, status, auto).await?;
+    }
+    if state.stage(tag) < Some(Stage::CodesignedAttested) && seen_tags_sigs.contains(tag) {
+        info!("Detached sigs for tag {} have been published", tag);
+        codesign(config, &version, state, status, auto).await?;
+    }
+    Ok(())
+}
+
+/// Resume jobs that were blocked on signing once gpg can sign without prompting again.
+async fn retry_blocked_jobs(
+    config: &Config,
+    seen_tags_sigs: &HashSet<String>,
+    state: &mut JobState,
+    status: &mut WatchStatus,
+    auto: bool,
+    dry_run: bool,
+) -> Result<()> {
+    if !state.is_blocked() {
+        return Ok(());
+    }
+    if let Err(blocked) = signing_backend(config).preflight() {
+        debug!("Jobs still blocked: {}", blocked);
+        return Ok(());
+    }
+    info!("Signing is unblocked, resuming blocked jobs");
+    resume_jobs(config, seen_tags_sigs, state, status, auto, dry_run).await
+}
+
+/// Process tags enqueued over the control socket until the queue is empty or the watcher is
+/// paused, continuing each tag from its last completed stage.
+async fn process_queued_tags(
+    config: &Config,
+    control: &Control,
+    seen_tags_sigs: &HashSet<String>,
+    state: &mut JobState,
+    status: &mut WatchStatus,
+    auto: bool,
+    dry_run: bool,
+) -> Result<()> {
+    while !control.is_paused() && !shutdown_requested() {
+        let Some(tag) = control.next_queued() else {
+            break;
+        };
+        status.set_queue(control.queued());
+        if dry_run {
+            info!("Skipping queued tag {tag} because --dry-run is enabled");
+            continue;
+        }
+        info!(
+            "Processing queued tag {} from stage: {:?}",
+            tag,
+            state.stage(&tag)
+        );
+        continue_tag(config, &tag, seen_tags_sigs, state, status, auto).await?;
+    }
+    status.set_queue(control.queued());
+    Ok(())
+}
+
+/// Record in the job state whether a stage of `tag` is blocked on signing.
+fn track_signing_blocked<T>(
+    state: &mut JobState,
+    tag: &BitcoinVersion,
+    result: Result<T>,
+) -> Result<T> {
+    match &result {
+        Ok(_) => state.clear_blocked(&tag.to_string())?,
+        Err(e) => {
+            if let Some(blocked) = e.downcast_ref::<SigningBlocked>() {
+                state.set_blocked(&tag.to_string(), &blocked.to_string())?;
+            }
+        }
+    }
+    result
+}
+
+/// Check whether guix-build outputs for a tag exist from an earlier session.
+fn build_outputs_exist(config: &Config, tag: &BitcoinVersion) -> bool {
+    let output_dir = config
+        .bitcoin_dir
+        .join(format!("guix-build-{}", tag.dir_name()))
+        .join("output");
+    output_dir
+        .read_dir()
+        .map(|mut entries| entries.next().is_some())
+        .unwrap_or(false)
+}
+
+/// Parse a tag and check it against the configured tag filter, logging and recording it when
+/// filtered out.
+///
+/// # Returns
+///
+/// A Result containing the parsed version if the tag should be processed.
+fn select_tag(config: &Config, tag: &str, state: &mut JobState) -> Result<Option<BitcoinVersion>> {
+    let reason = match tag.parse::<BitcoinVersion>() {
+        Ok(version) => match config.tag_filter.filter_reason(&version) {
+            None => return Ok(Some(version)),
+            Some(reason) => reason,
+        },
+        Err(e) => e.to_string(),
+    };
+    info!("Skipping tag {}: {}", tag, reason);
+    state.set_filtered(tag, &reason)?;
+    Ok(None)
+}
+
+/// Build a tag and record the completed stage.
+///
+/// The tag is recorded as started first, so a build that is interrupted is resumed on the next
+/// start.
+async fn build(
+    config: &Config,
+    tag: &BitcoinVersion,
+    state: &mut JobState,
+    status: &mut WatchStatus,
+    auto: bool,
+) -> Result<()> {
+    let args = BuildArgs {
+        action: BuildAction::Build,
+        tag: Some(tag.clone()),
+        auto,
+    };
+    let builder = create_builder(config, args)
+        .await
+        .context("Failed to initialize first guix builder in watcher")?;
+    state.set_stage(&tag.to_string(), Stage::Started)?;
+    status.start_job(&tag.to_string(), "build");
+    let result = builder.run().await;
+    status.finish_job();
+    result.with_context(|| format!("Build process for tag {} failed", tag))?;
+    state.set_stage(&tag.to_string(), Stage::Built)
+}
+
+/// Attest to the non-codesigned outputs of a built tag and record the completed stage.
+async fn attest_noncodesigned(
+    config: &Config,
+    tag: &BitcoinVersion,
+    state: &mut JobState,
+    status: &mut WatchStatus,
+    auto: bool,
+) -> Result<()> {
+    let args = BuildArgs {
+        action: BuildAction::NonCodeSigned,
+        tag: Some(tag.clone()),
+        auto,
+    };
+    let builder = create_builder(config, args)
+        .await
+        .context("Failed to initialize non-codesigned builder in watcher")?;
+    status.start_job(&tag.to_string(), "non-codesigned attestation");
+    let result = builder.run().await;
+    status.finish_job();
+    track_signing_blocked(state, tag, result)
+        .with_context(|| format!("Noncodesigned attestation process for tag {} failed", tag))?;
+    state.set_stage(&tag.to_string(), Stage::NonCodesignedAttested)
+}
+
+/// Codesign and attest to a tag and record the completed stage, which is PR opened only if the
+/// attestations were pushed to an open pull request.
+async fn codesign(
+    config: &Config,
+    tag: &BitcoinVersion,
+    state: &mut JobState,
+    status: &mut WatchStatus,
+    auto: bool,
+) -> Result<()> {
+    let args = BuildArgs {
+        action: BuildAction::CodeSigned,
+        tag: Some(tag.clone()),
+        auto,
+    };
+    let builder = create_builder(config, args)
+        .await
+        .context("Failed to initialize builder")?;
+    status.start_job(&tag.to_string(), "codesigning");
+    let result = builder.run_action().await;
+    status.finish_job();
+    let pull_request_open = track_signing_blocked(state, tag, result)
+        .with_context(|| format!("Codesigned attestation process for tag {} failed", tag))?;
+    let stage = if pull_request_open {
+        Stage::PrOpened
+    } else {
+        Stage::CodesignedAttested
+    };
+    state.set_stage(&tag.to_string(), stage)
+}
+
 async fn check_and_process_bitcoin_tags(
     config: &Config,
+    sources: &TagSources,
     seen_tags_bitcoin: &mut HashSet<String>,
-    in_progress: &mut HashSet<String>,
+    state: &mut JobState,
+    status: &mut WatchStatus,
+    auto: bool,
     dry_run: bool,
 ) -> Result<()> {
     info!("Checking for new bitcoin tags...");
-    match check_for_new_tags(
-        seen_tags_bitcoin,
-        &config.source_repo_owner,
-        &config.source_repo_name,
-    )
-    .await
-    {
+    match check_for_new_tags(sources.bitcoin.as_ref(), seen_tags_bitcoin, "bitcoin").await {
         Ok(new_tags) => {
             if !new_tags.is_empty() {
                 info!(
-                    "Detected {} new tags for {}/{}",
+                    "Detected {} new tags for {}",
                     new_tags.len(),
-                    &config.source_repo_owner,
-                    &config.source_repo_name
+                    sources.bitcoin.describe()
                 );
+                let mut selected = Vec::new();
                 for tag in new_tags {
-                    // TODO: check for auto here
-                    // args.auto = true;
-
+                    if let Some(version) = select_tag(config, &tag, state)? {
+                        selected.push((tag, version));
+                    }
+                }
+                status.set_queue(selected.iter().map(|(tag, _)| tag.clone()).collect());
+                for (tag, version) in selected {
                     if dry_run {
                         info!("Skipping build for tag {tag} because --dry-run is enabled");
                         continue;
                     }
-                    // Build first
-                    let mut args = BuildArgs {
-                        action: BuildAction::Build,
-                        tag: Some(tag.clone()),
-                        ..Default::default()
-                    };
-                    let builder = create_builder(config, args.clone())
-                        .await
-                        .context("Failed to initialize first guix builder in watcher")?;
-                    in_progress.insert(tag.clone());
-                    builder
-                        .run()
-                        .await
-                        .with_context(|| format!("Build process for tag {} failed", tag))?;
-
-                    // Then attest to noncodesigned
-                    args.action = BuildAction::NonCodeSigned;
-                    let builder = create_builder(config, args)
-                        .await
-                        .context("Failed to initialize non-codesigned builder in watcher")?;
-                    builder.run().await.with_context(|| {
-                        format!("Noncodesigned attestation process for tag {} failed", tag)
-                    })?;
+                    build(config, &version, state, status, auto).await?;
+                    attest_noncodesigned(config, &version, state, status, auto).await?;
                 }
+                status.set_queue(Vec::new());
             } else {
-                debug!(
-                    "No new tags for {}/{} found",
-                    &config.source_repo_owner, &config.source_repo_name
-                );
+                debug!("No new tags for {} found", sources.bitcoin.describe());
             }
         }
         Err(e) => {
             return Err(e).with_context(|| {
                 format!(
                     "Error checking for new tags in {}",
-                    &config.source_repo_name
+                    sources.bitcoin.describe()
                 )
             });
         }
@@ -128,62 +471,68 @@ async fn check_and_process_bitcoin_tags(
 
 async fn check_and_process_sigs_tags(
     config: &Config,
+    sources: &TagSources,
     seen_tags_sigs: &mut HashSet<String>,
-    in_progress: &mut HashSet<String>,
+    state: &mut JobState,
+    status: &mut WatchStatus,
+    auto: bool,
     dry_run: bool,
 ) -> Result<()> {
     info!("Checking for new bitcoin tags...");
-    match check_for_new_tags(
-        seen_tags_sigs,
-        &config.detached_repo_owner,
-        &config.detached_repo_name,
-    )
-    .await
-    {
+    match check_for_new_tags(sources.sigs.as_ref(), seen_tags_sigs, "sigs").await {
         Ok(new_tags) => {
             if !new_tags.is_empty() {
                 info!(
-                    "Detected {} new tags for {}/{}",
+                    "Detected {} new tags for {}",
                     new_tags.len(),
-                    &config.detached_repo_owner,
-                    &config.detached_repo_name
+                    sources.sigs.describe()
                 );
+                let mut selected = Vec::new();
                 for tag in new_tags {
-                    if in_progress.contains(&tag) {
-                        if dry_run {
-                            info!("Skipping build for sigs tag {tag} because --dry-run is enabled");
-                            continue;
-                        }
-                        let args = BuildArgs {
-                            action: BuildAction::CodeSigned,
-                            tag: Some(tag.clone()),
-                            ..Default::default()
-                        };
-                        let builder = create_builder(config, args)
-                            .await
-                            .context("Failed to initialize builder")?;
-                        builder.run().await.with_context(|| {
-                            format!("Codesigned attestation process for tag {} failed", tag)
-                        })?;
-                        in_progress.remove(&tag);
-                    } else {
-                        // TODO: Consider implementing the codesigning attempt here
-                        warn!("New tag detected in {}/{} was not in-progress (already built and non-codesigned) and so cannot be automatically codesigned", &config.detached_repo_owner, &config.detached_repo_name);
+                    let Some(version) = select_tag(config, &tag, state)? else {
+                        continue;
+                    };
+                    if state
+                        .stage(&tag)
+                        .is_some_and(|stage| stage >= Stage::CodesignedAttested)
+                    {
+                        debug!("Tag {} has already been codesigned", tag);
+                        continue;
+                    }
+                    selected.push((tag, version));
+                }
+                status.set_queue(selected.iter().map(|(tag, _)| tag.clone()).collect());
+                for (tag, version) in selected {
+                    let stage = state.stage(&tag);
+                    if dry_run {
+                        info!("Skipping build for sigs tag {tag} because --dry-run is enabled");
+                        continue;
+                    }
+                    if stage.is_none() && build_outputs_exist(config, &version) {
+                        info!(
+                            "Found existing guix-build outputs for tag {}, codesigning",
+                            tag
+                        );
+                        // Resumed by rebuilding if interrupted, as the outputs may be from a different build
+                        state.set_stage(&tag, Stage::Started)?;
+                    } else if stage < Some(Stage::Built) {
+                        info!(
+                            "No guix-build outputs found for tag {}, building before codesigning",
+                            tag
+                        );
+                        build(config, &version, state, status, auto).await?;
+                        attest_noncodesigned(config, &version, state, status, auto).await?;
                     }
+                    codesign(config, &version, state, status, auto).await?;
                 }
+                status.set_queue(Vec::new());
             } else {
-                debug!(
-                    "No new tags for {}/{} found",
-                    &config.detached_repo_owner, &config.detached_repo_name
-                );
+                debug!("No new tags for {} found", sources.sigs.describe());
             }
         }
         Err(e) => {
             return Err(e).with_context(|| {
-                format!(
-                    "Error checking for new tags in {}",
-                    &config.detached_repo_name
-                )
+                format!("Error checking for new tags in {}", sources.sigs.describe())
             });
         }
     }
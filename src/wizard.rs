@@ -5,7 +5,8 @@ use std::{
     path::PathBuf,
 };
 
-use crate::config::{get_config_file, Config, GH_TOKEN_NAME};
+use crate::config::{get_config_file, Config};
+use crate::forge::ForgeKind;
 
 pub(crate) async fn init_wizard() -> Result<()> {
     println!("Welcome to the bgt config wizard!");
@@ -31,14 +32,55 @@ pub(crate) async fn init_wizard() -> Result<()> {
 
     let guix_sigs_fork_url =
         prompt_input_with_validation("Enter the URL of your guix.sigs fork", |input| {
-            if input.starts_with("https://github.com") {
+            if input.starts_with("https://") || input.starts_with("http://") {
                 Ok(())
             } else {
-                Err("URL must start with 'https://github.com'")
+                Err("URL must start with 'https://' or 'http://'")
             }
         })
         .context("Failed to get valid guix.sigs fork URL")?;
 
+    // Self-hosted instances can't be recognised from the URL, so ask which API they speak
+    let forge = match ForgeKind::from_url(&guix_sigs_fork_url) {
+        Some(forge) => forge,
+        None => match prompt_input_with_validation(
+            "Which forge hosts your fork? (github/gitlab/gitea)",
+            |input| match input.to_lowercase().as_str() {
+                "github" | "gitlab" | "gitea" | "forgejo" => Ok(()),
+                _ => Err("Please enter 'github', 'gitlab' or 'gitea'"),
+            },
+        )
+        .context("Failed to get forge type")?
+        .to_lowercase()
+        .as_str()
+        {
+            "github" => ForgeKind::GitHub,
+            "gitlab" => ForgeKind::GitLab,
+            _ => ForgeKind::Gitea,
+        },
+    };
+    let default_config = Config::default();
+    let (guix_sigs_repo_owner, guix_sigs_repo_name) = if forge == ForgeKind::GitHub {
+        (
+            default_config.guix_sigs_repo_owner,
+            default_config.guix_sigs_repo_name,
+        )
+    } else {
+        let repo = prompt_input_with_validation(
+            &format!(
+                "Enter the guix.sigs repository on {} to open pull requests against (owner/name)",
+                forge
+            ),
+            |input| match input.split_once('/') {
+                Some((owner, name)) if !owner.is_empty() && !name.is_empty() => Ok(()),
+                _ => Err("Repository must be in the form 'owner/name'"),
+            },
+        )
+        .context("Failed to get guix.sigs repository")?;
+        let (owner, name) = repo.split_once('/').expect("validated above");
+        (owner.to_string(), name.to_string())
+    };
+
     let guix_build_dir = PathBuf::from(
         prompt_input(&format!(
             "Enter the path you want to use for the guix_build_dir (press Enter for default of {:?})",
@@ -48,7 +90,10 @@ pub(crate) async fn init_wizard() -> Result<()> {
     );
 
     let auto_open_prs = prompt_input_with_validation(
-        "Would you like to automatically open PRs on GitHub? (yes/no)",
+        &format!(
+            "Would you like to automatically open PRs on {}? (yes/no)",
+            forge
+        ),
         |input| {
             let input = input.to_lowercase();
             if input == "yes" || input == "no" {
@@ -63,13 +108,16 @@ pub(crate) async fn init_wizard() -> Result<()> {
         == "yes";
 
     let github_username = if auto_open_prs {
-        if std::env::var(GH_TOKEN_NAME).is_err() {
+        if std::env::var(forge.token_name()).is_err() {
             bail!(
                 "{} environment variable is not set. Please set it and run the wizard again.",
-                GH_TOKEN_NAME
+                forge.token_name()
             );
         }
-        Some(prompt_input("Enter your GitHub username").context("Failed to get GitHub username")?)
+        Some(
+            prompt_input(&format!("Enter your {} username", forge))
+                .context("Failed to get forge username")?,
+        )
     } else {
         None
     };
@@ -78,6 +126,9 @@ pub(crate) async fn init_wizard() -> Result<()> {
         gpg_key_id,
         signer_name,
         guix_sigs_fork_url,
+        forge,
+        guix_sigs_repo_owner,
+        guix_sigs_repo_name,
         guix_build_dir,
         github_username,
         ..Default::default()
@@ -103,11 +154,16 @@ pub(crate) async fn init_wizard() -> Result<()> {
     println!("Configuration saved to: {}", config_path.display());
     if auto_open_prs {
         println!(
-            "GitHub API Key ({}) is set in the environment.",
-            GH_TOKEN_NAME
+            "{} API Key ({}) is set in the environment.",
+            forge,
+            forge.token_name()
         );
     } else {
-        println!("Note: If you want to use GitHub features in the future, set the {} environment variable.", GH_TOKEN_NAME);
+        println!(
+            "Note: If you want to use {} features in the future, set the {} environment variable.",
+            forge,
+            forge.token_name()
+        );
     }
     Ok(())
 }